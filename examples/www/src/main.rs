@@ -41,11 +41,21 @@ enum Msg {
 
     ChangeFunction(ChangeData),
     ToggleReverse,
+    ChangeOrder(ChangeData),
+    ChangeSeed(ChangeData),
+    ChangeFill(ChangeData),
+    ChangeStrength(ChangeData),
+    ChangeSplit(ChangeData),
+    ChangeChannel(ChangeData),
 
     ToggleAlpha,
     ChangeMin(ChangeData),
     ChangeMax(ChangeData),
     ToggleInvert,
+
+    ChangeOpacity(ChangeData),
+    ChangeBlend(ChangeData),
+    ChangeFeather(ChangeData),
 }
 
 impl Component for Root {
@@ -217,6 +227,42 @@ impl Component for Root {
                 }
             }
             Msg::ToggleReverse => self.cfg.reverse ^= true,
+            Msg::ChangeOrder(ChangeData::Select(s)) => {
+                if let Some(v) = s.value() {
+                    if let Ok(t) = v.parse() {
+                        self.cfg.order = t;
+                    }
+                }
+            }
+            Msg::ChangeSeed(ChangeData::Value(s)) => {
+                if let Ok(v) = s.parse() {
+                    self.cfg.seed = v;
+                }
+            }
+            Msg::ChangeFill(ChangeData::Select(s)) => {
+                if let Some(v) = s.value() {
+                    if let Ok(t) = v.parse() {
+                        self.cfg.fill = t;
+                    }
+                }
+            }
+            Msg::ChangeStrength(ChangeData::Value(s)) => {
+                if let Ok(v) = s.parse() {
+                    self.cfg.strength = v;
+                }
+            }
+            Msg::ChangeSplit(ChangeData::Select(s)) => {
+                if let Some(v) = s.value() {
+                    if let Ok(t) = v.parse() {
+                        self.cfg.split = t;
+                    }
+                }
+            }
+            Msg::ChangeChannel(ChangeData::Select(s)) => {
+                if let Some(v) = s.value() {
+                    self.cfg.channel = v.parse().ok();
+                }
+            }
             Msg::ToggleAlpha => self.cfg.mask_alpha ^= true,
             Msg::ChangeMin(ChangeData::Value(s)) => {
                 if let Ok(v) = s.parse() {
@@ -229,6 +275,23 @@ impl Component for Root {
                 }
             }
             Msg::ToggleInvert => self.cfg.invert ^= true,
+            Msg::ChangeOpacity(ChangeData::Value(s)) => {
+                if let Ok(v) = s.parse() {
+                    self.cfg.opacity = v;
+                }
+            }
+            Msg::ChangeBlend(ChangeData::Select(s)) => {
+                if let Some(v) = s.value() {
+                    if let Ok(t) = v.parse() {
+                        self.cfg.blend = t;
+                    }
+                }
+            }
+            Msg::ChangeFeather(ChangeData::Value(s)) => {
+                if let Ok(v) = s.parse() {
+                    self.cfg.feather = v;
+                }
+            }
             _ => return false,
         }
 
@@ -384,6 +447,70 @@ impl Renderable<Root> for Root {
                                 onchange=|_| Msg::ToggleReverse,
                             />
                         </label>
+                        <label>
+                            {"Span order: "}
+                            <select onchange=|c| Msg::ChangeOrder(c), >
+                                {for ["ascending", "mountain", "valley", "alternate", "shuffle", "reverse"]
+                                    .iter()
+                                    .map(|v| html! {
+                                        <option value={v}, selected={self.cfg.order.to_string() == *v}, >{v}</option>
+                                    })}
+                            </select>
+                        </label>
+                        <label>
+                            {"Random seed: "}
+                            <input
+                                type="number",
+                                min="0",
+                                value={self.cfg.seed},
+                                onchange=|c| Msg::ChangeSeed(c),
+                            />
+                        </label>
+                        <label>
+                            {"Strength: "}
+                            <input
+                                type="number",
+                                min="0",
+                                max="1",
+                                step="0.05",
+                                value={self.cfg.strength},
+                                onchange=|c| Msg::ChangeStrength(c),
+                            />
+                        </label>
+                        <label>
+                            {"Fill spans instead: "}
+                            <select onchange=|c| Msg::ChangeFill(c), >
+                                {for ["none", "smear-first", "smear-max", "gradient", "average"]
+                                    .iter()
+                                    .map(|v| html! {
+                                        <option value={v}, selected={self.cfg.fill.to_string() == *v}, >{v}</option>
+                                    })}
+                            </select>
+                        </label>
+                        <label>
+                            {"Sort channels separately: "}
+                            <select onchange=|c| Msg::ChangeSplit(c), >
+                                {for ["none", "rgb", "rgba"]
+                                    .iter()
+                                    .map(|v| html! {
+                                        <option value={v}, selected={self.cfg.split.to_string() == *v}, >{v}</option>
+                                    })}
+                            </select>
+                        </label>
+                        <label>
+                            {"Sort one colour space channel: "}
+                            <select onchange=|c| Msg::ChangeChannel(c), >
+                                <option value="none", selected={self.cfg.channel.is_none()}, >{"none"}</option>
+                                {for ["hsv.h", "hsv.s", "hsv.v", "hsl.l", "ycbcr.y", "ycbcr.cb", "ycbcr.cr", "lab.l", "lab.a", "lab.b"]
+                                    .iter()
+                                    .map(|v| html! {
+                                        <option
+                                            value={v},
+                                            selected={self.cfg.channel.map(|c| c.to_string()).as_deref() == Some(*v)},
+                                        >{v}</option>
+                                    })}
+                            </select>
+                        </label>
                     </fieldset>
                     <fieldset>
                         <legend>{"Masking"}</legend>
@@ -424,6 +551,40 @@ impl Renderable<Root> for Root {
                             />
                         </label>
                     </fieldset>
+                    <fieldset>
+                        <legend>{"Blending"}</legend>
+                        <label>
+                            {"Opacity: "}
+                            <input
+                                type="number",
+                                min="0",
+                                max="1",
+                                step="0.05",
+                                value={self.cfg.opacity},
+                                onchange=|c| Msg::ChangeOpacity(c),
+                            />
+                        </label>
+                        <label>
+                            {"Blend mode: "}
+                            <select onchange=|c| Msg::ChangeBlend(c), >
+                                {for ["normal", "screen", "multiply", "difference", "lighten"]
+                                    .iter()
+                                    .map(|v| html! {
+                                        <option value={v}, selected={self.cfg.blend.to_string() == *v}, >{v}</option>
+                                    })}
+                            </select>
+                        </label>
+                        <label>
+                            {"Feather: "}
+                            <input
+                                type="number",
+                                min="0",
+                                max="100",
+                                value={self.cfg.feather},
+                                onchange=|c| Msg::ChangeFeather(c),
+                            />
+                        </label>
+                    </fieldset>
                     <br />
                     <button onclick=|_| Msg::DoSort, disabled={self.input.is_none()}, >
                         {"Sort some pixels!"}
@@ -0,0 +1,179 @@
+use std::fs::File;
+use std::io::{BufWriter, Read, Write};
+use std::path::Path;
+
+use gif::SetParameter;
+use image::{ImageError, ImageResult, Rgba, RgbaImage};
+
+/// How many times an animation plays.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Repeat {
+    /// Play this many extra times after the first; `Finite(0)` plays once.
+    Finite(u16),
+    /// Loop forever.
+    Infinite,
+}
+
+impl Default for Repeat {
+    fn default() -> Self {
+        Repeat::Finite(0)
+    }
+}
+
+/// A single frame of an animation.
+#[derive(Clone)]
+pub struct Frame {
+    /// The full canvas as it is displayed for this frame.
+    pub image: RgbaImage,
+    /// How long the frame is displayed, in hundredths of a second.
+    pub delay: u16,
+}
+
+/// A sequence of frames, decoded from or ready to be encoded as a GIF.
+///
+/// Frames are stored fully composited, so each one can be sorted on its own.
+/// Animated PNGs are not supported by the decoders this crate uses and are
+/// read as still images; the command line tool warns when it meets one.
+#[derive(Clone, Default)]
+pub struct Animation {
+    /// The frames in display order.
+    pub frames: Vec<Frame>,
+    /// How many times the animation plays.
+    pub repeat: Repeat,
+}
+
+impl Animation {
+    /// Repeat a still image as `frames` identical frames, looping forever.
+    #[must_use]
+    pub fn from_still(image: RgbaImage, frames: usize, delay: u16) -> Self {
+        Self {
+            frames: vec![Frame { image, delay }; frames],
+            repeat: Repeat::Infinite,
+        }
+    }
+
+    /// Open a GIF file.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be read or is not a valid GIF.
+    pub fn open<P: AsRef<Path>>(path: P) -> ImageResult<Self> {
+        Self::load(File::open(path)?)
+    }
+
+    /// Decode a GIF, compositing each frame onto the ones before it.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the data cannot be read or is not a valid GIF.
+    #[allow(clippy::cast_possible_truncation)]
+    pub fn load<R: Read>(mut reader: R) -> ImageResult<Self> {
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data)?;
+
+        let mut decoder = gif::Decoder::new(&data[..]);
+        decoder.set(gif::ColorOutput::RGBA);
+        let mut reader = decoder.read_info()?;
+
+        let mut canvas = RgbaImage::new(u32::from(reader.width()), u32::from(reader.height()));
+        let mut frames = Vec::new();
+
+        while let Some(frame) = reader.read_next_frame()? {
+            let previous = canvas.clone();
+            let (left, top) = (u32::from(frame.left), u32::from(frame.top));
+            let (width, height) = (u32::from(frame.width), u32::from(frame.height));
+
+            for (idx, px) in frame.buffer.chunks(4).enumerate() {
+                let (x, y) = (left + idx as u32 % width, top + idx as u32 / width);
+                if px[3] != 0 && x < canvas.width() && y < canvas.height() {
+                    canvas.put_pixel(x, y, Rgba([px[0], px[1], px[2], px[3]]));
+                }
+            }
+
+            frames.push(Frame {
+                image: canvas.clone(),
+                delay: frame.delay,
+            });
+
+            match frame.dispose {
+                gif::DisposalMethod::Background => {
+                    for y in top..(top + height).min(canvas.height()) {
+                        for x in left..(left + width).min(canvas.width()) {
+                            canvas.put_pixel(x, y, Rgba([0, 0, 0, 0]));
+                        }
+                    }
+                }
+                gif::DisposalMethod::Previous => canvas = previous,
+                _ => (),
+            }
+        }
+
+        Ok(Self {
+            frames,
+            repeat: read_repeat(&data),
+        })
+    }
+
+    /// Whether there is more than one frame.
+    #[must_use]
+    pub fn is_animated(&self) -> bool {
+        self.frames.len() > 1
+    }
+
+    /// Save as a GIF file.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be written.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> ImageResult<()> {
+        self.write_to(&mut BufWriter::new(File::create(path)?))
+    }
+
+    /// Encode as a GIF, preserving frame delays and the loop count.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if there are no frames, a frame is larger than a GIF
+    /// allows, or writing fails.
+    #[allow(clippy::cast_possible_truncation)]
+    pub fn write_to<W: Write>(&self, w: &mut W) -> ImageResult<()> {
+        let (width, height) = match self.frames.first() {
+            Some(f) => f.image.dimensions(),
+            None => return Err(ImageError::DimensionError),
+        };
+        if width > u32::from(u16::MAX) || height > u32::from(u16::MAX) {
+            return Err(ImageError::DimensionError);
+        }
+
+        let mut encoder = gif::Encoder::new(w, width as u16, height as u16, &[])?;
+        encoder.set(match self.repeat {
+            Repeat::Finite(n) => gif::Repeat::Finite(n),
+            Repeat::Infinite => gif::Repeat::Infinite,
+        })?;
+
+        for frame in &self.frames {
+            let mut pixels = frame.image.clone().into_raw();
+            let (w, h) = frame.image.dimensions();
+            let mut out = gif::Frame::from_rgba(w as u16, h as u16, &mut pixels);
+            out.delay = frame.delay;
+            out.dispose = gif::DisposalMethod::Background;
+            encoder.write_frame(&out)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Find the loop count in a GIF's NETSCAPE2.0 application extension.
+fn read_repeat(data: &[u8]) -> Repeat {
+    const HEADER: &[u8] = b"\x21\xFF\x0BNETSCAPE2.0\x03\x01";
+
+    data.windows(HEADER.len() + 2)
+        .find(|w| w.starts_with(HEADER))
+        .map_or(Repeat::Finite(0), |w| {
+            match u16::from(w[HEADER.len()]) | u16::from(w[HEADER.len() + 1]) << 8 {
+                0 => Repeat::Infinite,
+                n => Repeat::Finite(n),
+            }
+        })
+}
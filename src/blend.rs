@@ -0,0 +1,116 @@
+use std::ops::{Deref, DerefMut};
+
+use image::{ImageBuffer, Rgba};
+use strum_macros::{Display, EnumString};
+
+use crate::Channel;
+
+/// How sorted pixels are combined with the original image.
+#[derive(Clone, Copy, Debug, Display, EnumString, Eq, PartialEq)]
+#[strum(serialize_all = "snake_case")]
+pub enum BlendMode {
+    /// Replace the original with the sorted pixels.
+    Normal,
+    /// Brighten, as if projecting both images onto one screen.
+    Screen,
+    /// Darken by multiplying the two images.
+    Multiply,
+    /// Absolute difference between the two images.
+    Difference,
+    /// The brighter of the two images in each channel.
+    Lighten,
+}
+
+impl BlendMode {
+    fn apply(self, original: f32, sorted: f32) -> f32 {
+        match self {
+            BlendMode::Normal => sorted,
+            BlendMode::Screen => 1. - (1. - original) * (1. - sorted),
+            BlendMode::Multiply => original * sorted,
+            BlendMode::Difference => (original - sorted).abs(),
+            BlendMode::Lighten => original.max(sorted),
+        }
+    }
+}
+
+/// Soften the edges of a mask by averaging over a square of `radius` pixels
+/// around each one, then scale it by `opacity`. The mask holds one entry per
+/// pixel in row-major order.
+#[allow(clippy::cast_precision_loss)]
+pub(crate) fn feather(
+    mask: &[bool],
+    width: usize,
+    height: usize,
+    radius: usize,
+    opacity: f32,
+) -> Vec<f32> {
+    let weights = mask
+        .iter()
+        .map(|&m| if m { opacity } else { 0. })
+        .collect::<Vec<_>>();
+    if radius == 0 || width == 0 || height == 0 {
+        return weights;
+    }
+
+    let rows = weights
+        .chunks(width)
+        .flat_map(|row| box_blur(row, radius))
+        .collect::<Vec<_>>();
+
+    let mut out = vec![0.; rows.len()];
+    for x in 0..width {
+        let column = rows
+            .iter()
+            .skip(x)
+            .step_by(width)
+            .copied()
+            .collect::<Vec<_>>();
+        for (y, w) in box_blur(&column, radius).into_iter().enumerate() {
+            out[y * width + x] = w;
+        }
+    }
+
+    out
+}
+
+/// Average each value with those up to `radius` away, treating values beyond
+/// the ends as zero.
+#[allow(clippy::cast_precision_loss)]
+fn box_blur(values: &[f32], radius: usize) -> Vec<f32> {
+    let mut sums = vec![0.];
+    sums.extend(values.iter().scan(0., |acc, v| {
+        *acc += v;
+        Some(*acc)
+    }));
+
+    (0..values.len())
+        .map(|idx| {
+            let (start, end) = (
+                idx.saturating_sub(radius),
+                (idx + radius + 1).min(values.len()),
+            );
+            (sums[end] - sums[start]) / (radius * 2 + 1) as f32
+        })
+        .collect()
+}
+
+/// Blend sorted pixels over the original image, with the sorted image's
+/// weight at each pixel taken from `weights`.
+pub(crate) fn blend<T, C>(
+    sorted: &mut ImageBuffer<Rgba<T>, C>,
+    original: &ImageBuffer<Rgba<T>, Vec<T>>,
+    mode: BlendMode,
+    weights: &[f32],
+) where
+    T: Channel,
+    C: Deref<Target = [T]> + DerefMut,
+{
+    for ((s, o), &w) in sorted.pixels_mut().zip(original.pixels()).zip(weights) {
+        for (idx, (s, o)) in s.data.iter_mut().zip(&o.data).enumerate() {
+            let (s_f, o_f) = (Channel::to_f32(*s), Channel::to_f32(*o));
+            // colours are blended by the mode, but coverage only ever mixes
+            let target = if idx < 3 { mode.apply(o_f, s_f) } else { s_f };
+            *s = T::from_f32(o_f + (target - o_f) * w);
+        }
+    }
+}
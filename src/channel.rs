@@ -0,0 +1,102 @@
+use std::cmp::Ordering;
+
+use image::Primitive;
+
+use crate::heuristic::{wide_func, KeyFn};
+use crate::Heuristic;
+
+/// Subpixel types that images can be sorted in.
+///
+/// Implemented for `u8`, `u16` and `f32`, which covers ordinary images,
+/// 16-bit PNG and TIFF files, and HDR radiance data.
+pub trait Channel: Primitive + 'static {
+    /// Convert to a fraction of full scale, where `1.0` is the brightest
+    /// representable value (HDR data may exceed it).
+    fn to_f32(self) -> f32;
+
+    /// Convert from a fraction of full scale, clamping to the valid range.
+    fn from_f32(value: f32) -> Self;
+
+    /// The value equivalent to an 8-bit sorting threshold.
+    ///
+    /// `255` maps to the largest value of the type, so the default range
+    /// still includes HDR values above `1.0`.
+    fn from_threshold(value: u8) -> Self;
+
+    /// Total order used to sort keys.
+    fn compare(&self, other: &Self) -> Ordering;
+
+    /// Key extraction function for a heuristic.
+    fn key_fn(heuristic: Heuristic) -> KeyFn<Self>;
+}
+
+impl Channel for u8 {
+    fn to_f32(self) -> f32 {
+        f32::from(self) / 255.
+    }
+
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    fn from_f32(value: f32) -> Self {
+        (value * 255.).round().clamp(0., 255.) as u8
+    }
+
+    fn from_threshold(value: u8) -> Self {
+        value
+    }
+
+    fn compare(&self, other: &Self) -> Ordering {
+        self.cmp(other)
+    }
+
+    fn key_fn(heuristic: Heuristic) -> KeyFn<Self> {
+        heuristic.func()
+    }
+}
+
+impl Channel for u16 {
+    fn to_f32(self) -> f32 {
+        f32::from(self) / 65535.
+    }
+
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    fn from_f32(value: f32) -> Self {
+        (value * 65535.).round().clamp(0., 65535.) as u16
+    }
+
+    fn from_threshold(value: u8) -> Self {
+        u16::from(value) * 257
+    }
+
+    fn compare(&self, other: &Self) -> Ordering {
+        self.cmp(other)
+    }
+
+    fn key_fn(heuristic: Heuristic) -> KeyFn<Self> {
+        wide_func(heuristic)
+    }
+}
+
+impl Channel for f32 {
+    fn to_f32(self) -> f32 {
+        self
+    }
+
+    fn from_f32(value: f32) -> Self {
+        value.max(0.)
+    }
+
+    fn from_threshold(value: u8) -> Self {
+        match value {
+            255 => f32::MAX,
+            v => f32::from(v) / 255.,
+        }
+    }
+
+    fn compare(&self, other: &Self) -> Ordering {
+        self.partial_cmp(other).unwrap_or(Ordering::Equal)
+    }
+
+    fn key_fn(heuristic: Heuristic) -> KeyFn<Self> {
+        wide_func(heuristic)
+    }
+}
@@ -0,0 +1,227 @@
+use std::ffi::OsStr;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Read, Seek, Write};
+use std::path::Path;
+
+use image::hdr::{HDRDecoder, HDREncoder};
+use image::png::PNGEncoder;
+use image::tiff::TIFFDecoder;
+use image::{
+    ColorType, ImageBuffer, ImageDecoder, ImageError, ImageFormat, ImageResult, Rgb, Rgba,
+};
+
+/// An image with more than 8 bits per channel, which `DynamicImage` cannot hold.
+pub enum DeepImage {
+    /// 16 bits per channel, along with the colour type it was decoded from.
+    Rgba16(ImageBuffer<Rgba<u16>, Vec<u16>>, ColorType),
+    /// 32-bit floating point radiance, as stored in HDR files.
+    RgbaF32(ImageBuffer<Rgba<f32>, Vec<f32>>),
+}
+
+impl DeepImage {
+    /// Open an image file if it has more than 8 bits per channel.
+    ///
+    /// 16-bit PNG and TIFF files and Radiance HDR files are supported. Returns
+    /// `Ok(None)` for any other image, which should be opened with `image::open`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be read or decoded.
+    pub fn open<P: AsRef<Path>>(path: P) -> ImageResult<Option<Self>> {
+        Self::load(BufReader::new(File::open(path)?))
+    }
+
+    /// Decode an image from a reader if it has more than 8 bits per channel.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the data cannot be read or decoded.
+    pub fn load<R: BufRead + Seek>(mut reader: R) -> ImageResult<Option<Self>> {
+        // `guess_format` does not recognise TIFF headers
+        let magic = reader.fill_buf()?;
+        if magic.starts_with(b"II*\0") || magic.starts_with(b"MM\0*") {
+            return load_tiff(reader);
+        }
+
+        match image::guess_format(reader.fill_buf()?) {
+            Ok(ImageFormat::PNG) => load_png(reader),
+            Ok(ImageFormat::HDR) => load_hdr(reader),
+            _ => Ok(None),
+        }
+    }
+
+    /// Whether `format` can hold the image at its original depth.
+    #[must_use]
+    pub fn can_write(&self, format: ImageFormat) -> bool {
+        matches!(
+            (self, format),
+            (DeepImage::Rgba16(..), ImageFormat::PNG) | (DeepImage::RgbaF32(_), ImageFormat::HDR)
+        )
+    }
+
+    /// Save the image at its original depth, with the format chosen by the
+    /// file extension.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the format cannot hold this image's depth or the
+    /// file cannot be written.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> ImageResult<()> {
+        let path = path.as_ref();
+        let format = match path
+            .extension()
+            .and_then(OsStr::to_str)
+            .map(str::to_ascii_lowercase)
+            .as_deref()
+        {
+            Some("png") => ImageFormat::PNG,
+            Some("hdr") => ImageFormat::HDR,
+            _ => return Err(self.unsupported()),
+        };
+
+        let mut w = BufWriter::new(File::create(path)?);
+        self.write_to(&mut w, format)
+    }
+
+    /// Encode the image at its original depth. 16-bit images can be written
+    /// as PNG and HDR images as HDR.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the format cannot hold this image's depth or
+    /// writing fails.
+    pub fn write_to<W: Write>(&self, w: &mut W, format: ImageFormat) -> ImageResult<()> {
+        match (self, format) {
+            (DeepImage::Rgba16(buffer, color), ImageFormat::PNG) => {
+                let (width, height) = buffer.dimensions();
+                let bytes = from_rgba16(buffer, *color)
+                    .into_iter()
+                    .flat_map(|s| s.to_be_bytes().to_vec())
+                    .collect::<Vec<_>>();
+                PNGEncoder::new(w).encode(&bytes, width, height, *color)?;
+                Ok(())
+            }
+            (DeepImage::RgbaF32(buffer), ImageFormat::HDR) => {
+                let (width, height) = buffer.dimensions();
+                let pixels = buffer
+                    .pixels()
+                    .map(|Rgba { data }| Rgb([data[0], data[1], data[2]]))
+                    .collect::<Vec<_>>();
+                HDREncoder::new(w).encode(&pixels, width as usize, height as usize)?;
+                Ok(())
+            }
+            _ => Err(self.unsupported()),
+        }
+    }
+
+    fn unsupported(&self) -> ImageError {
+        ImageError::UnsupportedError(
+            match self {
+                DeepImage::Rgba16(..) => "16-bit images can only be saved as PNG",
+                DeepImage::RgbaF32(_) => "HDR images can only be saved as HDR",
+            }
+            .to_string(),
+        )
+    }
+}
+
+fn load_png<R: Read>(reader: R) -> ImageResult<Option<DeepImage>> {
+    use png::HasParameters;
+
+    // 16-bit PNGs are never paletted, so no expansion is needed
+    let mut decoder = png::Decoder::new(reader);
+    decoder.set(png::Transformations::IDENTITY);
+    let (info, mut reader) = decoder.read_info()?;
+
+    if info.bit_depth != png::BitDepth::Sixteen {
+        return Ok(None);
+    }
+
+    let mut data = vec![0; info.buffer_size()];
+    reader.next_frame(&mut data)?;
+
+    let color = ColorType::from((info.color_type, info.bit_depth));
+    let samples = data
+        .chunks(2)
+        .map(|b| u16::from_be_bytes([b[0], b[1]]))
+        .collect::<Vec<_>>();
+
+    to_rgba16(&samples, info.width, info.height, color)
+        .map(|buffer| Some(DeepImage::Rgba16(buffer, color)))
+}
+
+fn load_tiff<R: Read + Seek>(reader: R) -> ImageResult<Option<DeepImage>> {
+    let decoder = TIFFDecoder::new(reader)?;
+    let color = decoder.colortype();
+
+    match color {
+        ColorType::Gray(16) | ColorType::GrayA(16) | ColorType::RGB(16) | ColorType::RGBA(16) => {}
+        _ => return Ok(None),
+    }
+
+    #[allow(clippy::cast_possible_truncation)]
+    let (width, height) = {
+        let (w, h) = decoder.dimensions();
+        (w as u32, h as u32)
+    };
+    let samples = decoder
+        .read_image()?
+        .chunks(2)
+        .map(|b| u16::from_ne_bytes([b[0], b[1]]))
+        .collect::<Vec<_>>();
+
+    to_rgba16(&samples, width, height, color).map(|buffer| Some(DeepImage::Rgba16(buffer, color)))
+}
+
+fn load_hdr<R: BufRead>(reader: R) -> ImageResult<Option<DeepImage>> {
+    let decoder = HDRDecoder::new(reader)?;
+    let meta = decoder.metadata();
+    let data = decoder
+        .read_image_hdr()?
+        .into_iter()
+        .flat_map(|Rgb { data }| vec![data[0], data[1], data[2], 1.])
+        .collect();
+
+    ImageBuffer::from_raw(meta.width, meta.height, data)
+        .map(|buffer| Some(DeepImage::RgbaF32(buffer)))
+        .ok_or_else(|| ImageError::FormatError("HDR image data is truncated".to_string()))
+}
+
+/// Expand 16-bit samples of any supported colour type to RGBA.
+fn to_rgba16(
+    samples: &[u16],
+    width: u32,
+    height: u32,
+    color: ColorType,
+) -> ImageResult<ImageBuffer<Rgba<u16>, Vec<u16>>> {
+    let max = u16::MAX;
+    let data = match color {
+        ColorType::Gray(16) => samples.iter().flat_map(|&l| vec![l, l, l, max]).collect(),
+        ColorType::GrayA(16) => samples
+            .chunks(2)
+            .flat_map(|s| vec![s[0], s[0], s[0], s[1]])
+            .collect(),
+        ColorType::RGB(16) => samples
+            .chunks(3)
+            .flat_map(|s| vec![s[0], s[1], s[2], max])
+            .collect(),
+        ColorType::RGBA(16) => samples.to_vec(),
+        c => return Err(ImageError::UnsupportedColor(c)),
+    };
+
+    ImageBuffer::from_raw(width, height, data)
+        .ok_or_else(|| ImageError::FormatError("16-bit image data is truncated".to_string()))
+}
+
+/// Reduce RGBA back to the samples of the original colour type.
+fn from_rgba16(buffer: &ImageBuffer<Rgba<u16>, Vec<u16>>, color: ColorType) -> Vec<u16> {
+    buffer
+        .pixels()
+        .flat_map(|Rgba { data }| match color {
+            ColorType::Gray(_) => vec![data[0]],
+            ColorType::GrayA(_) => vec![data[0], data[3]],
+            ColorType::RGB(_) => vec![data[0], data[1], data[2]],
+            _ => data.to_vec(),
+        })
+        .collect()
+}
@@ -0,0 +1,93 @@
+use std::cmp::Ordering;
+
+use image::Rgba;
+use strum_macros::{Display, EnumString};
+
+use crate::Channel;
+
+/// What to write over each span in place of its sorted pixels.
+#[derive(Clone, Copy, Debug, Display, EnumString, Eq, PartialEq)]
+#[strum(serialize_all = "kebab_case")]
+pub enum Fill {
+    /// Sort the span as usual.
+    None,
+    /// Drag the first pixel of the span along it, or the last if sorting
+    /// in reverse.
+    SmearFirst,
+    /// Drag the pixel with the highest value along the span, or the lowest
+    /// if sorting in reverse.
+    SmearMax,
+    /// Blend smoothly from the lowest to the highest value in the span, in
+    /// the direction it would be sorted.
+    Gradient,
+    /// Fill the span with its average colour.
+    Average,
+}
+
+impl Fill {
+    /// Overwrite a span with synthesized pixels.
+    pub(crate) fn apply<T, F>(self, pixels: &mut [Rgba<T>], compare: F, descending: bool)
+    where
+        T: Channel,
+        F: Fn(&Rgba<T>, &Rgba<T>) -> Ordering,
+    {
+        let (first, last) = match (pixels.first(), pixels.last()) {
+            (Some(first), Some(last)) => (*first, *last),
+            _ => return,
+        };
+        let lowest = || pixels.iter().min_by(|l, r| compare(l, r)).copied();
+        let highest = || pixels.iter().max_by(|l, r| compare(l, r)).copied();
+
+        match self {
+            Fill::None => (),
+            Fill::SmearFirst => smear(pixels, if descending { last } else { first }),
+            Fill::SmearMax => {
+                let extreme = if descending { lowest() } else { highest() };
+                smear(pixels, extreme.unwrap_or(first));
+            }
+            Fill::Gradient => {
+                let (mut from, mut to) = (lowest().unwrap_or(first), highest().unwrap_or(last));
+                if descending {
+                    std::mem::swap(&mut from, &mut to);
+                }
+                gradient(pixels, from, to);
+            }
+            Fill::Average => smear(pixels, average(pixels)),
+        }
+    }
+}
+
+fn smear<T: Channel>(pixels: &mut [Rgba<T>], value: Rgba<T>) {
+    for p in pixels {
+        *p = value;
+    }
+}
+
+#[allow(clippy::cast_precision_loss)]
+fn gradient<T: Channel>(pixels: &mut [Rgba<T>], from: Rgba<T>, to: Rgba<T>) {
+    let steps = (pixels.len().max(2) - 1) as f32;
+
+    for (idx, p) in pixels.iter_mut().enumerate() {
+        let t = idx as f32 / steps;
+        for ((c, f), e) in p.data.iter_mut().zip(&from.data).zip(&to.data) {
+            let (f, e) = (Channel::to_f32(*f), Channel::to_f32(*e));
+            *c = T::from_f32(f + (e - f) * t);
+        }
+    }
+}
+
+#[allow(clippy::cast_precision_loss)]
+fn average<T: Channel>(pixels: &[Rgba<T>]) -> Rgba<T> {
+    let mut sums = [0.; 4];
+    for p in pixels {
+        for (s, c) in sums.iter_mut().zip(&p.data) {
+            *s += Channel::to_f32(*c);
+        }
+    }
+
+    let mut avg = pixels[0];
+    for (c, s) in avg.data.iter_mut().zip(&sums) {
+        *c = T::from_f32(s / pixels.len() as f32);
+    }
+    avg
+}
@@ -2,6 +2,11 @@ use image::Rgba;
 use strum::IntoEnumIterator;
 use strum_macros::{Display, EnumIter, EnumString, IntoStaticStr};
 
+use crate::Channel;
+
+/// Function extracting a sort key from a pixel.
+pub(crate) type KeyFn<T> = Box<dyn Fn(&Rgba<T>) -> T>;
+
 #[allow(clippy::trivially_copy_pass_by_ref)]
 fn pixel_max(Rgba { data, .. }: &Rgba<u8>) -> u8 {
     data[..3].iter().max().cloned().unwrap_or_default()
@@ -58,6 +63,63 @@ fn pixel_luma(Rgba { data, .. }: &Rgba<u8>) -> u8 {
     ((u16::from(data[0]) * 2 + u16::from(data[1]) + u16::from(data[2]) * 4) >> 3) as u8
 }
 
+fn unit_rgb<T: Channel>(Rgba { data, .. }: &Rgba<T>) -> (f32, f32, f32) {
+    (data[0].to_f32(), data[1].to_f32(), data[2].to_f32())
+}
+
+fn unit_max<T: Channel>(pixel: &Rgba<T>) -> f32 {
+    let (r, g, b) = unit_rgb(pixel);
+    r.max(g).max(b)
+}
+
+fn unit_min<T: Channel>(pixel: &Rgba<T>) -> f32 {
+    let (r, g, b) = unit_rgb(pixel);
+    r.min(g).min(b)
+}
+
+fn unit_hue<T: Channel>(pixel: &Rgba<T>) -> f32 {
+    let (r, g, b) = unit_rgb(pixel);
+    let (max, c) = (unit_max(pixel), unit_max(pixel) - unit_min(pixel));
+
+    let sector = if c <= 0. {
+        return 0.;
+    } else if (max - r).abs() < f32::EPSILON {
+        ((g - b) / c).rem_euclid(6.)
+    } else if (max - g).abs() < f32::EPSILON {
+        (b - r) / c + 2.
+    } else {
+        (r - g) / c + 4.
+    };
+
+    sector / 6.
+}
+
+/// Key functions for channels wider than 8 bits, computed in floating point.
+pub(crate) fn wide_func<T: Channel>(heuristic: Heuristic) -> KeyFn<T> {
+    match heuristic {
+        Heuristic::Red => Box::new(|Rgba { data, .. }| data[0]),
+        Heuristic::Green => Box::new(|Rgba { data, .. }| data[1]),
+        Heuristic::Blue => Box::new(|Rgba { data, .. }| data[2]),
+        Heuristic::Max | Heuristic::Value => Box::new(|p| T::from_f32(unit_max(p))),
+        Heuristic::Min => Box::new(|p| T::from_f32(unit_min(p))),
+        Heuristic::Chroma => Box::new(|p| T::from_f32(unit_max(p) - unit_min(p))),
+        Heuristic::Hue => Box::new(|p| T::from_f32(unit_hue(p))),
+        Heuristic::Saturation => Box::new(|p| match unit_max(p) {
+            v if v <= 0. => T::from_f32(0.),
+            v => T::from_f32((v - unit_min(p)) / v),
+        }),
+        Heuristic::Brightness => Box::new(|p| {
+            let (r, g, b) = unit_rgb(p);
+            T::from_f32((r + g + b) / 3.)
+        }),
+        Heuristic::Luma => Box::new(|p| {
+            let (r, g, b) = unit_rgb(p);
+            T::from_f32((r * 2. + g + b * 4.) / 8.)
+        }),
+        Heuristic::__Nonexhaustive => unreachable!(),
+    }
+}
+
 /// Basis to use for sorting individual pixels.
 #[allow(non_camel_case_types)]
 #[derive(Clone, Copy, Display, EnumIter, EnumString, Eq, IntoStaticStr, PartialEq)]
@@ -91,9 +153,16 @@ impl Heuristic {
         Self::iter().filter(|v| *v != Heuristic::__Nonexhaustive)
     }
 
+    /// Get the key extraction function for this heuristic on images of any
+    /// channel type.
+    #[must_use]
+    pub fn key<T: Channel>(self) -> KeyFn<T> {
+        T::key_fn(self)
+    }
+
     /// Get the key extraction function for this heuristic.
     #[must_use]
-    pub fn func(self) -> Box<dyn Fn(&Rgba<u8>) -> u8> {
+    pub fn func(self) -> KeyFn<u8> {
         match self {
             Heuristic::Red => Box::new(|Rgba { data, .. }| data[0]),
             Heuristic::Green => Box::new(|Rgba { data, .. }| data[1]),
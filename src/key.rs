@@ -0,0 +1,59 @@
+use std::fmt;
+use std::str::FromStr;
+
+use image::Rgba;
+
+use crate::Channel;
+
+/// A colour to exclude from sorting, such as a green-screen background.
+///
+/// Written as a hex triplet like `#00ff00` or as `r,g,b` with values from 0
+/// to 255.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct ColorKey(pub [u8; 3]);
+
+impl ColorKey {
+    /// Whether every colour channel of a pixel is within `tolerance` of the
+    /// key, on a scale of 0 to 255.
+    pub(crate) fn matches<T: Channel>(self, pixel: &Rgba<T>, tolerance: u8) -> bool {
+        pixel.data[..3].iter().zip(&self.0).all(|(&c, &k)| {
+            (Channel::to_f32(c) * 255. - f32::from(k)).abs() <= f32::from(tolerance)
+        })
+    }
+}
+
+impl FromStr for ColorKey {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let st = s.trim();
+        let err_msg = || format!("Could not parse `{}` as a colour", st);
+
+        let hex = st.strip_prefix('#').unwrap_or(st);
+        if hex.len() == 6 && !st.contains(',') {
+            let channel = |idx: usize| {
+                hex.get(idx * 2..idx * 2 + 2)
+                    .and_then(|c| u8::from_str_radix(c, 16).ok())
+                    .ok_or_else(err_msg)
+            };
+            return Ok(ColorKey([channel(0)?, channel(1)?, channel(2)?]));
+        }
+
+        match st
+            .split(',')
+            .map(|c| c.trim().parse::<u8>())
+            .collect::<Result<Vec<_>, _>>()
+            .as_deref()
+        {
+            Ok([r, g, b]) => Ok(ColorKey([*r, *g, *b])),
+            _ => Err(err_msg()),
+        }
+    }
+}
+
+impl fmt::Display for ColorKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let [r, g, b] = self.0;
+        write!(f, "#{:02x}{:02x}{:02x}", r, g, b)
+    }
+}
@@ -1,16 +1,63 @@
 //! Sort pixels in an image.
 #![warn(clippy::pedantic)]
 
-use image::{DynamicImage, Rgba};
-#[cfg(not(target_arch = "wasm32"))]
-use indicatif::{ProgressBar, ProgressStyle};
+use std::fmt;
+use std::ops::{Deref, DerefMut};
+use std::str::FromStr;
+
+use image::{ColorType, DynamicImage, ImageBuffer, Luma, LumaA, Rgba, RgbaImage};
 use structopt::StructOpt;
 
+use order::Arrangement;
+use rng::Rng;
+
+mod animation;
+mod blend;
+mod cancel;
+mod channel;
+mod deep;
+mod fill;
 mod heuristic;
+mod key;
+mod metadata;
+mod order;
 mod path;
-
+mod pipeline;
+mod progress;
+mod region;
+mod rng;
+mod save;
+mod sequence;
+mod space;
+mod split;
+mod sweep;
+
+pub use animation::{Animation, Frame, Repeat};
+pub use blend::BlendMode;
+pub use cancel::{CancelToken, Cancelled};
+pub use channel::Channel;
+pub use deep::DeepImage;
+pub use fill::Fill;
 pub use heuristic::Heuristic;
+pub use key::ColorKey;
+pub use metadata::Metadata;
+pub use order::Order;
 pub use path::Shape;
+pub use pipeline::Pipeline;
+pub use progress::Progress;
+pub use region::{Coord, Region};
+pub use save::{save_with_options, write_with_options, Compression, Filter, SaveOptions};
+pub use sequence::Sequence;
+pub use space::{ColorSpace, SpaceChannel};
+pub use split::Split;
+pub use sweep::{Param, Sweep};
+
+/// Observer called after each path is sorted, with the image and how many of
+/// the paths are done.
+type Watch<'a, T, C> = dyn FnMut(&ImageBuffer<Rgba<T>, C>, u64, u64) + 'a;
+
+/// The unsorted image and the weights it was blended back in with.
+type Blended<T> = (ImageBuffer<Rgba<T>, Vec<T>>, Vec<f32>);
 
 #[allow(clippy::needless_pass_by_value)]
 fn check_angle(angle: String) -> Result<(), String> {
@@ -24,11 +71,35 @@ fn check_angle(angle: String) -> Result<(), String> {
     }
 }
 
+#[allow(clippy::needless_pass_by_value)]
+fn check_fraction(fraction: String) -> Result<(), String> {
+    let frac = fraction
+        .parse::<f32>()
+        .map_err(|_| "Could not parse as a number".to_string())?;
+    if (0.0..=1.0).contains(&frac) {
+        Ok(())
+    } else {
+        Err("Value must be between 0 and 1".to_string())
+    }
+}
+
 /// Sorting configuration.
 ///
 /// Includes how to traverse the pixel grid, which regions of the image to skip,
 /// and what metric to sort by.
-#[derive(StructOpt)]
+///
+/// Sorting is deterministic: the same configuration and image always give
+/// the same output. Pixels that compare equal keep their order along the
+/// path unless `unstable` is set.
+///
+/// Horizontal and vertical lines sorted on anything but a `lab` channel use
+/// only exactly rounded arithmetic, so they give the same output on every
+/// platform. Angled lines, sine and ellipse paths and the `lab` colour space
+/// use the platform's `sin`, `cos`, `tan` and `powf`, which can round
+/// differently from one target to another, so they are only reproducible on
+/// the same platform.
+#[derive(Clone, StructOpt)]
+#[structopt(rename_all = "kebab-case")]
 pub struct Config {
     /// Minimum value to sort
     #[structopt(short, long = "min", default_value = "0")]
@@ -51,6 +122,37 @@ pub struct Config {
     /// Reverse the sort direction
     #[structopt(short, long)]
     pub reverse: bool,
+    /// How to arrange each span: ascending, mountain, valley, alternate,
+    /// shuffle, reverse or rotate(k)
+    #[structopt(
+        long,
+        default_value = "ascending",
+        raw(set = "structopt::clap::ArgSettings::NextLineHelp")
+    )]
+    pub order: Order,
+    /// How far to sort, from 0 to 1. Below 1, each pixel only moves part
+    /// of the way to its sorted position. Fills always cover the whole span
+    #[structopt(long, default_value = "1", raw(validator = "check_fraction"))]
+    pub strength: f32,
+    /// Use a faster sort that may put pixels that compare equal in a
+    /// different order in future versions
+    #[structopt(long)]
+    pub unstable: bool,
+    /// Seed for anything random, such as shuffled spans. The same seed
+    /// always gives the same result
+    #[structopt(long, default_value = "0")]
+    pub seed: u64,
+    /// Fill spans instead of sorting them: none, smear-first, smear-max,
+    /// gradient or average
+    #[structopt(long, default_value = "none")]
+    pub fill: Fill,
+    /// Sort colour channels independently of each other: none, rgb or rgba
+    #[structopt(long, default_value = "none")]
+    pub split: Split,
+    /// Sort one channel of a colour space, like `hsv.h`, `hsl.l`, `ycbcr.cr`
+    /// or `lab.a`, keeping the other channels in place. Overrides --split
+    #[structopt(long, raw(set = "structopt::clap::ArgSettings::NextLineHelp"))]
+    pub channel: Option<SpaceChannel>,
     /// Sort outside specified range rather than inside
     #[structopt(short, long)]
     pub invert: bool,
@@ -58,8 +160,17 @@ pub struct Config {
     #[structopt(short, long)]
     pub vertical: bool,
     /// Don't sort pixels that have zero alpha
-    #[structopt(short = "k", long)]
+    #[structopt(short = "k", long, raw(alias = "\"mask_alpha\""))]
     pub mask_alpha: bool,
+    /// Don't sort pixels with alpha below this value
+    #[structopt(long, default_value = "0")]
+    pub alpha_min: u8,
+    /// Don't sort pixels close to this colour, given as `#rrggbb` or `r,g,b`
+    #[structopt(long)]
+    pub key: Option<ColorKey>,
+    /// How far each channel may be from the key colour and still be excluded
+    #[structopt(long, default_value = "32")]
+    pub key_tolerance: u8,
     /// Rotate the sort path by a custom angle
     #[structopt(short, long, default_value = "0", raw(validator = "check_angle"))]
     pub angle: f32,
@@ -71,7 +182,21 @@ pub struct Config {
         raw(set = "structopt::clap::ArgSettings::NextLineHelp")
     )]
     pub path: Shape,
-    #[structopt(raw(hidden = "true"))]
+    /// Only sort inside a region: `x,y,w,h` or `polygon(x1,y1,x2,y2,...)`,
+    /// in pixels or percentages like `25%`
+    #[structopt(long, raw(set = "structopt::clap::ArgSettings::NextLineHelp"))]
+    pub region: Option<Region>,
+    /// Opacity of the sorted pixels over the original, from 0 to 1
+    #[structopt(long, default_value = "1", raw(validator = "check_fraction"))]
+    pub opacity: f32,
+    /// How to blend sorted pixels with the original: normal, screen,
+    /// multiply, difference or lighten
+    #[structopt(long, default_value = "normal")]
+    pub blend: BlendMode,
+    /// Soften the edges of sorted spans and regions over this many pixels
+    #[structopt(long, default_value = "0")]
+    pub feather: u32,
+    #[structopt(long = "__", raw(hidden = "true"))]
     __: bool,
 }
 
@@ -82,77 +207,441 @@ impl Default for Config {
             maximum: 255,
             function: Heuristic::Luma,
             reverse: false,
+            order: Order::Ascending,
+            strength: 1.0,
+            unstable: false,
+            seed: 0,
+            fill: Fill::None,
+            split: Split::None,
+            channel: None,
             invert: false,
             vertical: false,
             mask_alpha: false,
+            alpha_min: 0,
+            key: None,
+            key_tolerance: 32,
             angle: 0.0,
             path: Shape::Linear,
+            region: None,
+            opacity: 1.0,
+            blend: BlendMode::Normal,
+            feather: 0,
             __: false,
         }
     }
 }
 
 impl Config {
-    fn do_sort(&self, pixels: &mut [&Rgba<u8>]) {
-        let sort_fn = self.function.func();
-        let mask_fn = |p: &Rgba<u8>| !(self.mask_alpha && p.data[3] == 0);
+    /// Test whether a pixel is in the configured range and not masked out.
+    fn range_fn<T: Channel>(&self) -> impl Fn(&Rgba<T>) -> bool + '_ {
+        let key_fn = T::key_fn(self.function);
+        let (minimum, maximum) = (
+            T::from_threshold(self.minimum),
+            T::from_threshold(self.maximum),
+        );
+        let mask_fn = self.mask_fn();
+
+        move |p| {
+            let l = key_fn(p);
+            (l >= minimum && l <= maximum) != self.invert && mask_fn(p)
+        }
+    }
+
+    /// Test whether a pixel is left out of sorting by its alpha or by the
+    /// colour key.
+    pub(crate) fn mask_fn<T: Channel>(&self) -> impl Fn(&Rgba<T>) -> bool + '_ {
+        let alpha_min = f32::from(self.alpha_min) / 255.;
+
+        move |p| {
+            let alpha = p.data[3];
+            !(self.mask_alpha && alpha == T::from_threshold(0))
+                && Channel::to_f32(alpha) >= alpha_min
+                && !matches!(self.key, Some(k) if k.matches(p, self.key_tolerance))
+        }
+    }
+
+    /// Whether sorted pixels are blended with the original rather than
+    /// replacing it.
+    fn blends(&self) -> bool {
+        self.opacity < 1.0 || self.blend != BlendMode::Normal || self.feather > 0
+    }
+
+    /// Weight of the sorted image at each pixel when blending: the opacity
+    /// within spans and the region, feathered at their edges.
+    fn blend_weights<T: Channel>(
+        &self,
+        original: &ImageBuffer<Rgba<T>, Vec<T>>,
+        mask: Option<&[bool]>,
+    ) -> Vec<f32> {
+        let (width, height) = original.dimensions();
+        let in_range = self.range_fn();
+        let mut spans = match mask {
+            Some(m) => m.to_vec(),
+            None => original.pixels().map(in_range).collect(),
+        };
+        if let Some(region) = &self.region {
+            for (s, r) in spans.iter_mut().zip(region.mask(width, height)) {
+                *s &= r;
+            }
+        }
+
+        blend::feather(
+            &spans,
+            width as usize,
+            height as usize,
+            self.feather as usize,
+            self.opacity,
+        )
+    }
+
+    /// Sort each span of `pixels`. Spans are the runs of pixels in the
+    /// configured range, or of those marked in `mask` if one is given.
+    fn do_sort<T: Channel>(&self, pixels: &mut [Rgba<T>], mask: Option<&[bool]>, rng: &mut Rng) {
+        let sort_fn = T::key_fn(self.function);
+        let in_range = self.range_fn();
+        let in_span = |idx: usize, p: &Rgba<T>| match mask {
+            Some(m) => m[idx],
+            None => in_range(p),
+        };
+
+        let compare = |l: &Rgba<T>, r: &Rgba<T>| sort_fn(l).compare(&sort_fn(r));
+
+        let mut arrangement = Arrangement {
+            order: self.order,
+            descending: self.reverse,
+            strength: self.strength,
+            stable: !self.unstable,
+            rng,
+        };
 
-        let mut ctr = 0;
+        let (mut ctr, mut n_spans) = (0, 0);
         while ctr < pixels.len() as usize {
             // find the end of the current "good" sequence
-            let numel = pixels[ctr..]
-                .iter()
-                .take_while(|p| {
-                    let l = sort_fn(p);
-                    (l >= self.minimum && l <= self.maximum) != self.invert && mask_fn(p)
-                })
+            let numel = (ctr..pixels.len())
+                .take_while(|&idx| in_span(idx, &pixels[idx]))
                 .count();
 
             // sort
-            pixels[ctr..ctr + numel].sort_unstable_by(|l, r| {
-                if self.reverse {
-                    sort_fn(r).cmp(&sort_fn(l))
-                } else {
-                    sort_fn(l).cmp(&sort_fn(r))
+            let span = &mut pixels[ctr..ctr + numel];
+            arrangement.descending = self.reverse != self.order.flips(n_spans);
+            match (self.channel, self.split) {
+                _ if self.fill != Fill::None => {
+                    self.fill.apply(span, compare, arrangement.descending);
                 }
-            });
+                (Some(channel), _) => channel.arrange(span, &mut arrangement),
+                (None, Split::None) => arrangement.arrange(span, compare),
+                (None, split) => split.arrange(span, &mut arrangement),
+            }
+            n_spans += 1;
 
             ctr += numel;
 
             // continue until another value in the right range appears
-            ctr += pixels[ctr..]
-                .iter()
-                .take_while(|p| {
-                    let l = sort_fn(p);
-                    (l < self.minimum || l > self.maximum) != self.invert || !mask_fn(p)
-                })
+            ctr += (ctr..pixels.len())
+                .take_while(|&idx| !in_span(idx, &pixels[idx]))
                 .count();
         }
     }
 
+    #[must_use]
+    /// Sort pixels according to configured settings and return a new image.
+    ///
+    /// The new image has the same colour type as the original.
+    pub fn sort(&self, img: DynamicImage) -> DynamicImage {
+        self.sort_with_progress(img, &())
+    }
+
+    #[must_use]
+    /// Sort pixels like [`sort`](#method.sort), reporting progress as paths are completed.
+    pub fn sort_with_progress(&self, img: DynamicImage, progress: &dyn Progress) -> DynamicImage {
+        match self.try_sort(img, progress, &CancelToken::new()) {
+            Ok(img) => img,
+            Err(Cancelled) => unreachable!("no other handle to the token exists"),
+        }
+    }
+
+    /// Sort pixels, stopping early if `cancel` is triggered.
+    ///
+    /// Cancellation is checked before each path is traversed. A cancelled sort
+    /// returns [`Cancelled`](struct.Cancelled.html) and discards the partially
+    /// sorted image.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err(Cancelled)` if the token was cancelled before sorting finished.
+    pub fn try_sort(
+        &self,
+        img: DynamicImage,
+        progress: &dyn Progress,
+        cancel: &CancelToken,
+    ) -> Result<DynamicImage, Cancelled> {
+        let color = img.color();
+        self.try_sort_buffer(into_rgba(img), progress, cancel)
+            .map(|rgba| from_rgba(rgba, color))
+    }
+
+    /// Sort the pixels of an existing buffer in place.
+    ///
+    /// The result is the same as [`sort`](#method.sort), but vertical sorting
+    /// does not rotate the buffer, so this works on any container that derefs
+    /// to a slice of channels, e.g. a borrowed `&mut [u8]` of tightly packed
+    /// RGBA data. Any [`Channel`](trait.Channel.html) type can be used.
+    ///
+    /// Straight line paths are sorted without copying the image. Sine and
+    /// ellipse paths overlap one another and must all read from the unsorted
+    /// image, so a copy is made for them, as it is for blending.
+    pub fn sort_in_place<T, C>(&self, img: &mut ImageBuffer<Rgba<T>, C>)
+    where
+        T: Channel,
+        C: Deref<Target = [T]> + DerefMut,
+    {
+        match self.try_sort_in_place(img, &(), &CancelToken::new()) {
+            Ok(()) => (),
+            Err(Cancelled) => unreachable!("no other handle to the token exists"),
+        }
+    }
+
+    /// Sort the pixels of an existing buffer in place, stopping early if
+    /// `cancel` is triggered.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err(Cancelled)` if the token was cancelled before sorting
+    /// finished. The buffer is then left partially sorted.
+    pub fn try_sort_in_place<T, C>(
+        &self,
+        img: &mut ImageBuffer<Rgba<T>, C>,
+        progress: &dyn Progress,
+        cancel: &CancelToken,
+    ) -> Result<(), Cancelled>
+    where
+        T: Channel,
+        C: Deref<Target = [T]> + DerefMut,
+    {
+        self.sort_and_blend(img, None, progress, cancel, &mut |_, _, _| ())
+            .map(|_| ())
+    }
+
+    /// Sort an owned buffer of any [`Channel`](trait.Channel.html) type,
+    /// stopping early if `cancel` is triggered.
+    ///
+    /// This is how images deeper than 8 bits, which `DynamicImage` cannot
+    /// hold, are sorted at full precision.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err(Cancelled)` if the token was cancelled before sorting finished.
+    pub fn try_sort_buffer<T: Channel>(
+        &self,
+        buffer: ImageBuffer<Rgba<T>, Vec<T>>,
+        progress: &dyn Progress,
+        cancel: &CancelToken,
+    ) -> Result<ImageBuffer<Rgba<T>, Vec<T>>, Cancelled> {
+        self.try_sort_masked(buffer, None, progress, cancel)
+    }
+
+    /// Sort an owned buffer, with spans taken from `mask` if one is given.
+    /// The mask holds one entry per pixel in row-major order.
+    pub(crate) fn try_sort_masked<T: Channel>(
+        &self,
+        mut buffer: ImageBuffer<Rgba<T>, Vec<T>>,
+        mask: Option<&[bool]>,
+        progress: &dyn Progress,
+        cancel: &CancelToken,
+    ) -> Result<ImageBuffer<Rgba<T>, Vec<T>>, Cancelled> {
+        self.sort_and_blend(&mut buffer, mask, progress, cancel, &mut |_, _, _| ())?;
+        Ok(buffer)
+    }
+
+    /// Sort every path of `img`, calling `watch` after each one, and blend
+    /// the result with the unsorted image if configured to. When blending,
+    /// the unsorted image and blend weights are returned so that frames
+    /// recorded by `watch` can be blended the same way.
+    fn sort_and_blend<T, C>(
+        &self,
+        img: &mut ImageBuffer<Rgba<T>, C>,
+        mask: Option<&[bool]>,
+        progress: &dyn Progress,
+        cancel: &CancelToken,
+        watch: &mut Watch<T, C>,
+    ) -> Result<Option<Blended<T>>, Cancelled>
+    where
+        T: Channel,
+        C: Deref<Target = [T]> + DerefMut,
+    {
+        // overlapping paths must all read from the unsorted image, and
+        // blending needs it afterwards
+        let snapshot = match self.path {
+            Shape::Linear if !self.blends() => None,
+            _ => ImageBuffer::from_raw(img.width(), img.height(), img.to_vec()),
+        };
+
+        self.sort_paths(
+            img,
+            snapshot
+                .as_ref()
+                .filter(|_| !matches!(self.path, Shape::Linear)),
+            mask,
+            progress,
+            cancel,
+            watch,
+        )?;
+
+        Ok(snapshot.filter(|_| self.blends()).map(|original| {
+            let weights = self.blend_weights(&original, mask);
+            blend::blend(img, &original, self.blend, &weights);
+            (original, weights)
+        }))
+    }
+
+    /// Record the sort as it happens, as an animation of `frames` frames
+    /// running from the unsorted image to the sorted one.
+    ///
+    /// Frames in between are taken after an even share of the paths has been
+    /// sorted, so they show the sort sweeping across the image. The last frame
+    /// is always the same image [`sort`](#method.sort) gives, so with `frames`
+    /// at 0 or 1 that is the only frame.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err(Cancelled)` if the token was cancelled before sorting finished.
+    pub fn try_sort_progressive(
+        &self,
+        mut buffer: RgbaImage,
+        frames: usize,
+        delay: u16,
+        progress: &dyn Progress,
+        cancel: &CancelToken,
+    ) -> Result<Animation, Cancelled> {
+        let steps = frames.saturating_sub(1) as u64;
+        let mut images = vec![buffer.clone()];
+        let blended = self.sort_and_blend(
+            &mut buffer,
+            None,
+            progress,
+            cancel,
+            &mut |img, done, len| {
+                while (images.len() as u64) <= steps && done * steps >= images.len() as u64 * len {
+                    images.push(img.clone());
+                }
+            },
+        )?;
+
+        // the first frame stays unsorted, so it is left unblended too
+        if let Some((original, weights)) = blended {
+            for img in images.iter_mut().skip(1) {
+                blend::blend(img, &original, self.blend, &weights);
+            }
+        }
+
+        // the last frame is always the sorted image, even with a single frame
+        // or an image too small to have any paths left to show
+        images.truncate(frames.max(1) - 1);
+        images.resize(frames.max(1), buffer);
+
+        Ok(Animation {
+            frames: images
+                .into_iter()
+                .map(|image| Frame { image, delay })
+                .collect(),
+            repeat: Repeat::Infinite,
+        })
+    }
+
+    /// Sort the pixels along one path, reading from `src` if given. With a
+    /// `region`, the path is cut into the runs that lie inside it.
+    fn sort_path<T, C>(
+        &self,
+        img: &mut ImageBuffer<Rgba<T>, C>,
+        src: Option<&ImageBuffer<Rgba<T>, Vec<T>>>,
+        mask: Option<&[bool]>,
+        region: Option<&[bool]>,
+        idxes: &[(u32, u32)],
+        rng: &mut Rng,
+    ) where
+        T: Channel,
+        C: Deref<Target = [T]> + DerefMut,
+    {
+        let width = img.width() as usize;
+        match region {
+            Some(r) => idxes
+                .split(|(x, y)| !r[*y as usize * width + *x as usize])
+                .filter(|run| !run.is_empty())
+                .for_each(|run| self.sort_run(img, src, mask, run, rng)),
+            None => self.sort_run(img, src, mask, idxes, rng),
+        }
+    }
+
+    /// Sort the pixels along an unbroken run of a path.
+    fn sort_run<T, C>(
+        &self,
+        img: &mut ImageBuffer<Rgba<T>, C>,
+        src: Option<&ImageBuffer<Rgba<T>, Vec<T>>>,
+        mask: Option<&[bool]>,
+        idxes: &[(u32, u32)],
+        rng: &mut Rng,
+    ) where
+        T: Channel,
+        C: Deref<Target = [T]> + DerefMut,
+    {
+        let mut pixels = idxes
+            .iter()
+            .map(|(x, y)| match src {
+                Some(s) => *s.get_pixel(*x, *y),
+                None => *img.get_pixel(*x, *y),
+            })
+            .collect::<Vec<_>>();
+        let width = img.width() as usize;
+        let spans = mask.map(|m| {
+            idxes
+                .iter()
+                .map(|(x, y)| m[*y as usize * width + *x as usize])
+                .collect::<Vec<_>>()
+        });
+        self.do_sort(&mut pixels[..], spans.as_deref(), rng);
+
+        for ((idx_x, idx_y), px) in idxes.iter().zip(pixels) {
+            img.put_pixel(*idx_x, *idx_y, px);
+        }
+    }
+
     #[allow(
         clippy::cast_possible_truncation,
         clippy::cast_precision_loss,
         clippy::cast_sign_loss,
         clippy::too_many_lines
     )]
-    #[must_use]
-    /// Sort pixels according to configured settings and return a new image.
-    pub fn sort(&self, mut img: DynamicImage) -> DynamicImage {
-        if self.vertical {
-            img = img.rotate90();
-        }
-
-        let mut rgba = img.to_rgba();
-        let (w, h) = rgba.dimensions();
-
-        #[cfg(not(target_arch = "wasm32"))]
-        let prog = {
-            let p = ProgressBar::new(u64::from(h));
-            p.set_style(
-                ProgressStyle::default_bar().template("{prefix} {wide_bar} {pos:>5}/{len}"),
-            );
-            p
+    /// Sort every path in turn, calling `watch` after each one.
+    fn sort_paths<T, C>(
+        &self,
+        img: &mut ImageBuffer<Rgba<T>, C>,
+        src: Option<&ImageBuffer<Rgba<T>, Vec<T>>>,
+        mask: Option<&[bool]>,
+        progress: &dyn Progress,
+        cancel: &CancelToken,
+        watch: &mut Watch<T, C>,
+    ) -> Result<(), Cancelled>
+    where
+        T: Channel,
+        C: Deref<Target = [T]> + DerefMut,
+    {
+        // paths are traced as if the image were rotated by 90 degrees when
+        // sorting vertically, then mapped back to the real coordinates
+        let (img_w, img_h) = img.dimensions();
+        let (w, h) = if self.vertical {
+            (img_h, img_w)
+        } else {
+            (img_w, img_h)
+        };
+        let region = self.region.as_ref().map(|r| r.mask(img_w, img_h));
+        let mut rng = Rng::new(self.seed);
+        let vertical = self.vertical;
+        let to_img = move |(x, y): (u32, u32)| {
+            if vertical {
+                (y, img_h - 1 - x)
+            } else {
+                (x, y)
+            }
         };
 
         match self.path {
@@ -167,18 +656,17 @@ impl Config {
                 );
                 let n_shells = diag * 5 * (1. + eccentricity).powi(2).floor() as u32;
 
-                #[cfg(not(target_arch = "wasm32"))]
-                {
-                    prog.set_prefix("Sorting rings:");
-                    prog.set_length(u64::from(n_shells));
-                    prog.set_draw_delta(u64::from(n_shells) / 50);
-                }
+                let len = u64::from(n_shells);
+                progress.start("Sorting rings", len);
 
                 let cos = self.angle.to_radians().cos();
                 let sin = self.angle.to_radians().sin();
 
-                let rgba_c = rgba.clone();
-                for a in (0..n_shells).rev().map(|da| (da as f32) / 5.) {
+                for (done, a) in (1..).zip((0..n_shells).rev().map(|da| (da as f32) / 5.)) {
+                    if cancel.is_cancelled() {
+                        return Err(Cancelled);
+                    }
+
                     let b_sq = a.powi(2) * (1. - eccentricity.powi(2));
                     let c = (a.powi(2) - b_sq).sqrt();
                     let peri = (std::f32::consts::PI * 2. * ((a.powi(2) + b_sq) / 2.).sqrt())
@@ -197,21 +685,13 @@ impl Config {
                                 None
                             }
                         })
+                        .map(to_img)
                         .collect::<Vec<_>>();
                     idxes.dedup();
 
-                    let mut pixels = idxes
-                        .iter()
-                        .map(|(x, y)| rgba_c.get_pixel(*x, *y))
-                        .collect::<Vec<_>>();
-                    self.do_sort(&mut pixels[..]);
-
-                    for ((idx_x, idx_y), px) in idxes.iter().zip(pixels.iter()) {
-                        rgba.put_pixel(*idx_x, *idx_y, **px);
-                    }
-
-                    #[cfg(not(target_arch = "wasm32"))]
-                    prog.inc(1);
+                    self.sort_path(img, src, mask, region.as_deref(), &idxes, &mut rng);
+                    progress.inc(1);
+                    watch(img, done, len);
                 }
             }
             Shape::Sine {
@@ -225,18 +705,17 @@ impl Config {
                     (w as f32).hypot(h as f32).floor() as u32,
                 );
 
-                #[cfg(not(target_arch = "wasm32"))]
-                {
-                    prog.set_prefix("Sorting rows:");
-                    prog.set_length(u64::from(h + diag) * 3);
-                    prog.set_draw_delta(u64::from(h + diag) * 3 / 50);
-                }
+                let len = u64::from(diag) * 3;
+                progress.start("Sorting rows", len);
 
                 let ang = self.angle.to_radians();
                 let (sin, cos) = (ang.sin(), ang.cos());
 
-                let rgba_c = rgba.clone();
-                for row_idx in 0..(diag * 3) {
+                for (done, row_idx) in (1..).zip(0..(diag * 3)) {
+                    if cancel.is_cancelled() {
+                        return Err(Cancelled);
+                    }
+
                     let idxes = (0..diag)
                         .map(|x| x as f32)
                         .map(|x| {
@@ -255,19 +734,12 @@ impl Config {
                                 None
                             }
                         })
+                        .map(to_img)
                         .collect::<Vec<_>>();
-                    let mut pixels = idxes
-                        .iter()
-                        .map(|(x, y)| rgba_c.get_pixel(*x, *y))
-                        .collect::<Vec<_>>();
-                    self.do_sort(&mut pixels[..]);
-
-                    for ((idx_x, idx_y), px) in idxes.iter().zip(pixels.iter()) {
-                        rgba.put_pixel(*idx_x, *idx_y, **px);
-                    }
 
-                    #[cfg(not(target_arch = "wasm32"))]
-                    prog.inc(1);
+                    self.sort_path(img, src, mask, region.as_deref(), &idxes, &mut rng);
+                    progress.inc(1);
+                    watch(img, done, len);
                 }
             }
             Shape::Linear if self.angle != 0.0 => {
@@ -279,72 +751,176 @@ impl Config {
                     0..(i64::from(h) - extra_height)
                 };
 
-                #[cfg(not(target_arch = "wasm32"))]
-                {
-                    prog.set_prefix("Sorting rows:");
-                    prog.set_draw_delta((u64::from(h) + extra_height.abs() as u64) / 50);
-                }
+                let len = (range.end - range.start) as u64;
+                progress.start("Sorting rows", len);
+
+                for (done, row_idx) in (1..).zip(range) {
+                    if cancel.is_cancelled() {
+                        return Err(Cancelled);
+                    }
 
-                let rgba_c = rgba.clone();
-                for row_idx in range {
                     let idxes = (0..w)
                         .map(|xv| (xv, (xv as f32 * tan + row_idx as f32) as u32))
                         .filter(|(_, y)| *y > 0 && *y < h)
+                        .map(to_img)
                         .collect::<Vec<_>>();
-                    let mut pixels = idxes
-                        .iter()
-                        .map(|(x, y)| rgba_c.get_pixel(*x, *y))
-                        .collect::<Vec<_>>();
-                    self.do_sort(&mut pixels[..]);
 
-                    for ((idx_x, idx_y), px) in idxes.iter().zip(pixels.iter()) {
-                        rgba.put_pixel(*idx_x, *idx_y, **px);
-                    }
-
-                    #[cfg(not(target_arch = "wasm32"))]
-                    prog.inc(1);
+                    self.sort_path(img, src, mask, region.as_deref(), &idxes, &mut rng);
+                    progress.inc(1);
+                    watch(img, done, len);
                 }
             }
             Shape::Linear => {
-                #[cfg(not(target_arch = "wasm32"))]
-                {
-                    prog.set_draw_delta(u64::from(h) / 50);
-                    prog.set_prefix(&format!(
-                        "Sorting {}:",
-                        if self.vertical { "columns" } else { "rows" }
-                    ));
-                    prog.tick();
-                }
+                progress.start(
+                    if self.vertical {
+                        "Sorting columns"
+                    } else {
+                        "Sorting rows"
+                    },
+                    u64::from(h),
+                );
 
-                for (idx_y, row) in rgba
-                    .clone()
-                    .pixels()
-                    .collect::<Vec<_>>()
-                    .chunks_mut(w as usize)
-                    .enumerate()
-                {
-                    self.do_sort(&mut row[..]);
-
-                    for (idx_x, px) in row.iter().enumerate() {
-                        rgba.put_pixel(idx_x as u32, idx_y as u32, **px);
+                for (done, idx_y) in (1..).zip(0..h) {
+                    if cancel.is_cancelled() {
+                        return Err(Cancelled);
                     }
 
-                    #[cfg(not(target_arch = "wasm32"))]
-                    prog.inc(1);
+                    let idxes = (0..w)
+                        .map(|idx_x| to_img((idx_x, idx_y)))
+                        .collect::<Vec<_>>();
+
+                    self.sort_path(img, src, mask, region.as_deref(), &idxes, &mut rng);
+                    progress.inc(1);
+                    watch(img, done, u64::from(h));
                 }
             }
             _ => unreachable!(),
         }
 
-        #[cfg(not(target_arch = "wasm32"))]
-        prog.finish_with_message("Done sorting!");
+        progress.finish();
+
+        Ok(())
+    }
+}
+
+/// Take the RGBA buffer out of an image, converting only if necessary.
+pub(crate) fn into_rgba(img: DynamicImage) -> RgbaImage {
+    match img {
+        DynamicImage::ImageRgba8(rgba) => rgba,
+        img => img.to_rgba(),
+    }
+}
+
+/// Convert a sorted RGBA buffer back to the colour type of the original image.
+///
+/// Sorting only moves pixels around, so grey images stay grey and opaque
+/// images stay opaque, and dropping the extra channels loses nothing.
+pub(crate) fn from_rgba(rgba: RgbaImage, color: ColorType) -> DynamicImage {
+    let (w, h) = rgba.dimensions();
+    match color {
+        ColorType::Gray(_) => DynamicImage::ImageLuma8(ImageBuffer::from_fn(w, h, |x, y| {
+            Luma([rgba.get_pixel(x, y).data[0]])
+        })),
+        ColorType::GrayA(_) => DynamicImage::ImageLumaA8(ImageBuffer::from_fn(w, h, |x, y| {
+            let Rgba { data } = rgba.get_pixel(x, y);
+            LumaA([data[0], data[3]])
+        })),
+        ColorType::RGB(_) => DynamicImage::ImageRgb8(DynamicImage::ImageRgba8(rgba).to_rgb()),
+        ColorType::BGR(_) => DynamicImage::ImageBgr8(DynamicImage::ImageRgba8(rgba).to_bgr()),
+        ColorType::BGRA(_) => DynamicImage::ImageBgra8(DynamicImage::ImageRgba8(rgba).to_bgra()),
+        _ => DynamicImage::ImageRgba8(rgba),
+    }
+}
+
+/// Split a pass description into arguments, honouring single and double quotes.
+fn split_args(s: &str) -> Result<Vec<String>, String> {
+    let mut args = Vec::new();
+    let mut current = None;
+    let mut quote = None;
+
+    for c in s.chars() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            None if c == '"' || c == '\'' => {
+                quote = Some(c);
+                current.get_or_insert_with(String::new);
+            }
+            None if c.is_whitespace() => args.extend(current.take()),
+            _ => current.get_or_insert_with(String::new).push(c),
+        }
+    }
+
+    if quote.is_some() {
+        return Err(format!("Unterminated quote in `{}`", s));
+    }
+
+    args.extend(current);
+    Ok(args)
+}
 
-        let mut img_out = DynamicImage::ImageRgba8(rgba);
+/// Parse a single pass from its command line representation, e.g.
+/// `--function=hue --angle=30`.
+impl FromStr for Config {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let args = split_args(s)?;
+        Self::from_iter_safe(std::iter::once("pass".to_string()).chain(args)).map_err(|e| e.message)
+    }
+}
+
+/// Render a pass as command line arguments that parse back to the same value.
+impl fmt::Display for Config {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "--min={} --max={} --function={} --angle={} --path={} --seed={}",
+            self.minimum, self.maximum, self.function, self.angle, self.path, self.seed
+        )?;
+        if let Some(region) = &self.region {
+            write!(f, " --region={}", region)?;
+        }
+        if self.order != Order::Ascending {
+            write!(f, " --order={}", self.order)?;
+        }
+        if self.strength < 1.0 {
+            write!(f, " --strength={}", self.strength)?;
+        }
+        if self.fill != Fill::None {
+            write!(f, " --fill={}", self.fill)?;
+        }
+        if self.split != Split::None {
+            write!(f, " --split={}", self.split)?;
+        }
+        if let Some(channel) = self.channel {
+            write!(f, " --channel={}", channel)?;
+        }
+        if self.alpha_min > 0 {
+            write!(f, " --alpha-min={}", self.alpha_min)?;
+        }
+        if let Some(key) = self.key {
+            write!(f, " --key={} --key-tolerance={}", key, self.key_tolerance)?;
+        }
+        if self.blends() {
+            write!(
+                f,
+                " --opacity={} --blend={} --feather={}",
+                self.opacity, self.blend, self.feather
+            )?;
+        }
 
-        if self.vertical {
-            img_out = img_out.rotate270();
+        for (set, flag) in &[
+            (self.reverse, "--reverse"),
+            (self.invert, "--invert"),
+            (self.vertical, "--vertical"),
+            (self.mask_alpha, "--mask-alpha"),
+            (self.unstable, "--unstable"),
+        ] {
+            if *set {
+                write!(f, " {}", flag)?;
+            }
         }
 
-        img_out
+        Ok(())
     }
 }
@@ -1,55 +1,978 @@
-use std::path::PathBuf;
+use std::cell::{Cell, RefCell};
+use std::collections::HashSet;
+use std::error::Error;
+use std::ffi::OsStr;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Cursor, Read, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{mpsc, Mutex};
+use std::thread;
 
-use image::ImageError;
+use image::{DynamicImage, ImageError, ImageFormat};
+use indicatif::{ProgressBar, ProgressStyle};
 use structopt::StructOpt;
 
-use pxsort::Config;
+use pxsort::{
+    save_with_options, write_with_options, Animation, CancelToken, Compression, Config, DeepImage,
+    Filter, Metadata, Pipeline, Progress, SaveOptions, Sequence, Sweep,
+};
+
+/// How to report sorting progress on stderr.
+enum ProgressFormat {
+    Bar,
+    Json,
+    None,
+}
+
+impl FromStr for ProgressFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "bar" => Ok(ProgressFormat::Bar),
+            "json" => Ok(ProgressFormat::Json),
+            "none" => Ok(ProgressFormat::None),
+            _ => Err(format!("Unknown progress format `{}`", s)),
+        }
+    }
+}
+
+/// Draw a terminal progress bar for each stage.
+#[derive(Default)]
+struct Bar(RefCell<Option<ProgressBar>>);
+
+impl Progress for Bar {
+    fn start(&self, label: &str, len: u64) {
+        let p = ProgressBar::new(len);
+        p.set_style(ProgressStyle::default_bar().template("{prefix} {wide_bar} {pos:>5}/{len}"));
+        p.set_prefix(&format!("{}:", label));
+        p.set_draw_delta(len / 50);
+        *self.0.borrow_mut() = Some(p);
+    }
+
+    fn inc(&self, delta: u64) {
+        if let Some(p) = &*self.0.borrow() {
+            p.inc(delta);
+        }
+    }
+
+    fn finish(&self) {
+        if let Some(p) = self.0.borrow_mut().take() {
+            p.finish_with_message("Done sorting!");
+        }
+    }
+}
+
+/// Write one JSON object per line, at most once per percent of progress.
+#[derive(Default)]
+struct Json {
+    pos: Cell<u64>,
+    len: Cell<u64>,
+    percent: Cell<u64>,
+}
+
+impl Progress for Json {
+    fn start(&self, label: &str, len: u64) {
+        self.pos.set(0);
+        self.len.set(len);
+        self.percent.set(0);
+        eprintln!(r#"{{"event":"start","stage":{:?},"len":{}}}"#, label, len);
+    }
+
+    fn inc(&self, delta: u64) {
+        let pos = self.pos.get() + delta;
+        self.pos.set(pos);
+
+        let percent = pos * 100 / self.len.get().max(1);
+        if percent > self.percent.get() {
+            self.percent.set(percent);
+            eprintln!(
+                r#"{{"event":"progress","pos":{},"len":{}}}"#,
+                pos,
+                self.len.get()
+            );
+        }
+    }
+
+    fn finish(&self) {
+        eprintln!(r#"{{"event":"finish"}}"#);
+    }
+}
 
 #[derive(StructOpt)]
 #[structopt(about = "Sort the pixels in an image")]
 #[structopt(raw(global_setting = "structopt::clap::AppSettings::ColoredHelp"))]
 #[structopt(rename_all = "kebab-case")]
 struct Cli {
-    /// Input file
-    #[structopt(parse(try_from_str))]
-    file: PathBuf,
-    /// Output file
+    /// Input files or directories, or `-` to read from stdin
+    #[structopt(parse(try_from_str), raw(required_unless = r#""dump-config""#))]
+    files: Vec<PathBuf>,
+    /// Output file, or output directory when sorting several files. Use `-`
+    /// to write to stdout, which needs --format
     #[structopt(short, long = "out", parse(try_from_str))]
     output: Option<PathBuf>,
+    /// Output format, overriding the output file's extension: png, jpeg,
+    /// gif, bmp, ico, pnm, or hdr for HDR input only. WebP and TIFF cannot be
+    /// written
+    #[structopt(long, parse(try_from_str = "parse_format"))]
+    format: Option<ImageFormat>,
+    /// JPEG quality, from 1 to 100
+    #[structopt(long, default_value = "75", raw(validator = "check_quality"))]
+    quality: u8,
+    /// PNG compression: default, fast, best, huffman or rle
+    #[structopt(long, default_value = "fast")]
+    compression: Compression,
+    /// PNG row filter: none, sub, up, avg or paeth
+    #[structopt(long, default_value = "sub")]
+    filter: Filter,
+    /// Also sort images in subdirectories of input directories
+    #[structopt(short = "R", long)]
+    recursive: bool,
+    /// Output file name, filled in with {stem}, {ext} and {function}. {ext}
+    /// is png for inputs that cannot be written back, like TIFF. {n} counts
+    /// up from 1 to the first name that is not taken. Inputs with the output
+    /// name of another input beside them are skipped
+    #[structopt(long, default_value = "{stem}_{n}.{ext}")]
+    name: String,
+    /// Skip inputs whose first output name already exists
+    #[structopt(long)]
+    skip_existing: bool,
+    /// Overwrite existing output files
+    #[structopt(long)]
+    force: bool,
+    /// Number of files to sort at once [default: number of CPUs]
+    #[structopt(short, long)]
+    jobs: Option<usize>,
+    /// Add a sorting pass, written with the options below (may be repeated)
+    #[structopt(
+        long = "pass",
+        raw(number_of_values = "1", allow_hyphen_values = "true")
+    )]
+    passes: Vec<Config>,
+    /// Read sorting passes from a file, one per line
+    #[structopt(long, parse(try_from_str))]
+    pipeline: Option<PathBuf>,
+    /// Animate a parameter from one value to another, written as
+    /// `param=start..end` (may be repeated)
+    #[structopt(
+        long = "animate",
+        raw(number_of_values = "1", allow_hyphen_values = "true")
+    )]
+    sweeps: Vec<Sweep>,
+    /// Record the sort as it happens, as a GIF or as numbered images
+    #[structopt(long, raw(conflicts_with = r#""sweeps""#))]
+    progressive: bool,
+    /// Number of frames to render when animating a still image, or per pass
+    /// when recording the sort
+    #[structopt(long, default_value = "30")]
+    frames: usize,
+    /// How long each rendered frame is shown, in hundredths of a second
+    #[structopt(long, default_value = "4")]
+    delay: u16,
+    /// Treat the input as a directory of numbered video frames, sorted in
+    /// frame number order into the output directory with stable spans
+    #[structopt(long)]
+    sequence: bool,
+    /// How far a pixel must cross a threshold before it joins or leaves a
+    /// span in the next frame of a sequence
+    #[structopt(long, default_value = "8")]
+    hysteresis: u8,
+    /// Print the sorting passes in pipeline file format and exit
+    #[structopt(long)]
+    dump_config: bool,
+    /// Don't print progress or status messages
+    #[structopt(short, long)]
+    quiet: bool,
+    /// How to report progress: bar, json or none
+    #[structopt(long, default_value = "bar")]
+    progress: ProgressFormat,
     #[structopt(flatten)]
     config: Config,
 }
 
-fn main() -> Result<(), ImageError> {
-    let cli = Cli::from_args();
+/// Everything needed to sort one file, shared by every file in a batch.
+struct Job {
+    pipeline: Pipeline,
+    sweeps: Vec<Sweep>,
+    progressive: bool,
+    frames: usize,
+    delay: u16,
+    name: String,
+    save: SaveOptions,
+    force: bool,
+    quiet: bool,
+}
+
+impl Job {
+    /// Fill in the output name template for an input file.
+    fn output_name(&self, file_in: &Path, n: usize) -> Result<String, Box<dyn Error>> {
+        let stem = file_in.file_stem().ok_or("Invalid filename")?;
+        // the extension follows --format if it is given; otherwise sweeps and
+        // recordings are saved as animations by default, and formats that
+        // cannot be written, like TIFF, as PNG, which also keeps 16-bit images
+        // at full depth
+        let ext = match (self.save.format, file_in.extension()) {
+            (Some(f), _) => format_extension(f).into(),
+            _ if !self.sweeps.is_empty() || self.progressive => "gif".into(),
+            (None, Some(_)) if !is_writable(file_in) => "png".into(),
+            (None, ext) => ext.unwrap_or_default().to_string_lossy(),
+        };
+        let function = self
+            .pipeline
+            .passes()
+            .iter()
+            .map(|pass| pass.function.to_string())
+            .collect::<Vec<_>>()
+            .join("-");
+
+        let name = self
+            .name
+            .replace("{stem}", &stem.to_string_lossy())
+            .replace("{ext}", &ext)
+            .replace("{function}", &function)
+            .replace("{n}", &n.to_string());
+        Ok(name.trim_end_matches('.').to_string())
+    }
+
+    /// Choose where in `dir` to save an input file. Unless overwriting is
+    /// allowed, `{n}` is counted up past names that exist or are `claimed`
+    /// by other files in this run.
+    fn output_path(
+        &self,
+        file_in: &Path,
+        dir: &Path,
+        claimed: &mut HashSet<PathBuf>,
+    ) -> Result<PathBuf, Box<dyn Error>> {
+        let mut n = 1;
+        loop {
+            let path = dir.join(self.output_name(file_in, n)?);
+            let taken = path.exists() || claimed.contains(&path);
+            if !taken || self.force || !self.name.contains("{n}") {
+                claimed.insert(path.clone());
+                return Ok(path);
+            }
+            n += 1;
+        }
+    }
+
+    /// Find the files among `files` that this job saves for another of them
+    /// when writing next to its input, so that running it again over the same
+    /// directory does not sort its own earlier output.
+    fn earlier_outputs(&self, files: &[&Path]) -> Result<HashSet<PathBuf>, Box<dyn Error>> {
+        let inputs = files.iter().collect::<HashSet<_>>();
+        let mut outputs = HashSet::new();
+        for file in files {
+            let dir = file.parent().unwrap_or_else(|| Path::new(""));
+            for n in 1.. {
+                let path = dir.join(self.output_name(file, n)?);
+                if path == *file || !inputs.contains(&path.as_path()) {
+                    break;
+                }
+                outputs.insert(path);
+                if !self.name.contains("{n}") {
+                    break;
+                }
+            }
+        }
+        Ok(outputs)
+    }
+
+    /// Sort one image file and save the result.
+    fn sort_file(
+        &self,
+        file_in: &Path,
+        file_out: &Path,
+        progress: &dyn Progress,
+    ) -> Result<(), Box<dyn Error>> {
+        if !is_stdio(file_out) {
+            if same_file(file_in, file_out) {
+                return Err(format!("{:?} is both the input and the output", file_in).into());
+            }
+            if file_out.exists() && !self.force {
+                return Err(
+                    format!("{:?} already exists; use --force to overwrite", file_out).into(),
+                );
+            }
+        }
+
+        if !self.quiet {
+            eprintln!("Opening image at {:?}", file_in);
+        }
+
+        let data = read_input(file_in)?;
+        let format_in = image::guess_format(&data)
+            .ok()
+            .or_else(|| extension_format(file_in));
+        if format_in == Some(ImageFormat::PNG) && is_apng(&data) {
+            eprintln!(
+                "Warning: {:?} is an animated PNG; only its still image is sorted",
+                file_in
+            );
+        }
+        // still images are turned upright as they are decoded
+        let mut meta = Metadata::read(&data);
+        let mut decode = || {
+            let img = match format_in {
+                Some(f) => image::load_from_memory_with_format(&data, f),
+                None => image::load_from_memory(&data),
+            }?;
+            Ok::<_, ImageError>(meta.apply_orientation(img))
+        };
+
+        // without an extension, the output is written in the same format as the input
+        let format = match (self.save.format, file_out.extension(), format_in) {
+            (Some(f), _, _) => Some(f),
+            _ if is_stdio(file_out) => return Err("Writing to stdout needs --format".into()),
+            (None, Some(_), _) => None,
+            (None, None, Some(f)) => Some(f),
+            (None, None, None) => return Err("Could not determine the output format".into()),
+        };
+        // only HDR input has the floating point samples HDR files hold
+        if output_format(file_out, format) == Some(ImageFormat::HDR)
+            && format_in != Some(ImageFormat::HDR)
+        {
+            return Err("Only HDR images can be saved as HDR".into());
+        }
+        let gif_out = format == Some(ImageFormat::GIF)
+            || file_out
+                .extension()
+                .is_some_and(|e| e.eq_ignore_ascii_case("gif"));
+
+        if self.progressive {
+            let img = decode()?.to_rgba();
+            let anim_out = self.pipeline.try_sort_progressive(
+                img,
+                self.frames,
+                self.delay,
+                progress,
+                &CancelToken::new(),
+            )?;
+
+            if gif_out {
+                if !self.quiet {
+                    eprintln!("Saving file to {:?}", file_out);
+                }
+                anim_out.write_to(&mut create(file_out)?)?;
+            } else if is_stdio(file_out) {
+                return Err("Numbered frames cannot be written to stdout".into());
+            } else {
+                // other formats get one numbered file per frame
+                let digits = anim_out.frames.len().to_string().len().max(4);
+                for (idx, frame) in anim_out.frames.into_iter().enumerate() {
+                    let path = numbered(file_out, idx + 1, digits);
+                    if !self.quiet {
+                        eprintln!("Saving file to {:?}", path);
+                    }
+                    let img_out = DynamicImage::ImageRgba8(frame.image);
+                    write_image(&img_out, &path, format, &self.save, &meta)?;
+                }
+            }
+
+            return Ok(());
+        }
+
+        if !self.sweeps.is_empty() && !gif_out {
+            return Err("Animated output must be saved as a GIF".into());
+        }
+
+        // animated GIFs have every frame sorted, as long as the output can hold them
+        let animation = match format_in {
+            Some(ImageFormat::GIF) if gif_out => {
+                Some(Animation::load(&data[..])?).filter(Animation::is_animated)
+            }
+            _ => None,
+        };
+        let animation = match animation {
+            None if !self.sweeps.is_empty() => Some(Animation::from_still(
+                decode()?.to_rgba(),
+                self.frames,
+                self.delay,
+            )),
+            anim => anim,
+        };
+
+        // images deeper than 8 bits are sorted and saved at full precision
+        // when the output can hold them, and otherwise like any other image
+        let deep = match output_format(file_out, format) {
+            Some(f) if f == ImageFormat::PNG || f == ImageFormat::HDR => {
+                DeepImage::load(Cursor::new(&data[..]))?
+                    .filter(|img| img.can_write(f))
+                    .map(|img| (img, f))
+            }
+            _ => None,
+        };
+
+        if let Some(anim) = animation {
+            let anim_out = self.pipeline.try_sweep_animation(
+                anim,
+                &self.sweeps,
+                progress,
+                &CancelToken::new(),
+            )?;
+
+            if !self.quiet {
+                eprintln!("Saving file to {:?}", file_out);
+            }
+            anim_out.write_to(&mut create(file_out)?)?;
+        } else if let Some((img, f)) = deep {
+            let img = meta.apply_deep_orientation(img);
+            let img_out = self
+                .pipeline
+                .try_sort_deep(img, progress, &CancelToken::new())?;
+
+            if !self.quiet {
+                eprintln!("Saving file to {:?}", file_out);
+            }
+            let mut data = Vec::new();
+            img_out.write_to(&mut data, f)?;
+            write_embedded(file_out, data, &meta)?;
+        } else {
+            let img = decode()?;
+            let img_out = self.pipeline.sort_with_progress(img, progress);
+
+            if !self.quiet {
+                eprintln!("Saving file to {:?}", file_out);
+            }
+            write_image(&img_out, file_out, format, &self.save, &meta)?;
+        }
 
-    eprintln!("Opening image at {:?}", cli.file);
-    let img = image::open(&cli.file)?;
+        Ok(())
+    }
+}
 
-    let img_out = cli.config.sort(img);
+fn main() -> Result<(), Box<dyn Error>> {
+    let matches = Cli::clap().get_matches();
+    let cli = Cli::from_clap(&matches);
 
-    let file_out = if let Some(p) = cli.output {
-        p
+    let seed = cli.config.seed;
+    let mut pipeline = match &cli.pipeline {
+        Some(p) => fs::read_to_string(p)?.parse()?,
+        None => Pipeline::new(),
+    };
+    pipeline.extend(cli.passes);
+    if pipeline.is_empty() {
+        pipeline.push(cli.config);
     } else {
-        match (
-            cli.file.parent(),
-            cli.file.file_stem(),
-            cli.file.extension(),
-        ) {
-            (None, _, _) | (_, None, _) | (_, _, None) => panic!("Invalid filename"),
-            (Some(p), Some(b), Some(e)) => {
-                let mut fname = b.to_owned();
-                fname.push("_1.");
-                fname.push(e);
-                let mut pth = p.to_owned();
-                pth.push(fname);
-                pth
+        // sorting options outside of the passes would otherwise be lost;
+        // only the seed is shared by every pass
+        let mut config = cli.config;
+        config.seed = Config::default().seed;
+        if config.to_string() != Config::default().to_string() {
+            return Err("With --pass or --pipeline, sorting options go inside each pass".into());
+        }
+    }
+    // a seed on the command line applies to every pass, even those read
+    // from --pass or --pipeline
+    if matches.occurrences_of("seed") > 0 {
+        pipeline.set_seed(seed);
+    }
+
+    if cli.dump_config {
+        print!("{}", pipeline);
+        return Ok(());
+    }
+
+    for sweep in &cli.sweeps {
+        if !pipeline.passes().iter().any(|pass| sweep.applies_to(pass)) {
+            return Err(format!("`{}` has no effect on the chosen path", sweep.param).into());
+        }
+    }
+
+    let progress: Box<dyn Progress> = match (cli.quiet, cli.progress) {
+        (true, _) | (_, ProgressFormat::None) => Box::new(()),
+        (_, ProgressFormat::Bar) => Box::new(Bar::default()),
+        (_, ProgressFormat::Json) => Box::new(Json::default()),
+    };
+
+    let save = SaveOptions {
+        format: cli.format,
+        jpeg_quality: cli.quality,
+        png_compression: cli.compression,
+        png_filter: cli.filter,
+    };
+
+    if cli.sequence {
+        let dir_in = match &cli.files[..] {
+            [dir] => dir,
+            _ => return Err("A sequence is read from a single directory".into()),
+        };
+        let dir_out = match cli.output {
+            Some(p) => p,
+            None => {
+                let mut name = dir_in
+                    .file_name()
+                    .ok_or("Invalid directory name")?
+                    .to_owned();
+                name.push("_1");
+                dir_in.with_file_name(name)
             }
+        };
+        let sequence = Sequence::new(pipeline, cli.hysteresis);
+        return sort_sequence(
+            dir_in, &dir_out, sequence, &save, &*progress, cli.force, cli.quiet,
+        );
+    }
+
+    let job = Job {
+        pipeline,
+        sweeps: cli.sweeps,
+        progressive: cli.progressive,
+        frames: cli.frames,
+        delay: cli.delay,
+        name: cli.name,
+        save,
+        force: cli.force,
+        quiet: cli.quiet,
+    };
+    let mut claimed = HashSet::new();
+
+    // a single file is sorted with progress reported and any error returned
+    if let [file_in] = &cli.files[..] {
+        if !file_in.is_dir() {
+            let dir = file_in.parent().unwrap_or_else(|| Path::new(""));
+            if cli.skip_existing && dir.join(job.output_name(file_in, 1)?).exists() {
+                return Ok(());
+            }
+            let file_out = match cli.output {
+                Some(p) => p,
+                // stdin is written to stdout unless told otherwise
+                None if is_stdio(file_in) => file_in.clone(),
+                None => job.output_path(file_in, dir, &mut claimed)?,
+            };
+            return job.sort_file(file_in, &file_out, &*progress);
         }
+    }
+
+    let mut images = Vec::new();
+    for input in &cli.files {
+        images.extend(find_images(input, cli.recursive)?);
+    }
+    let earlier =
+        job.earlier_outputs(&images.iter().map(|(f, _)| f.as_path()).collect::<Vec<_>>())?;
+
+    let mut tasks = Vec::new();
+    for (file_in, subdir) in images {
+        let dir_out = match &cli.output {
+            Some(dir) => dir.join(subdir),
+            None => file_in.parent().unwrap_or_else(|| Path::new("")).to_owned(),
+        };
+        // only skip earlier outputs when this run saves into their directory too
+        let beside = dir_out.join(file_in.file_name().unwrap_or_default());
+        if earlier.contains(&file_in) && same_file(&file_in, &beside) {
+            if !cli.quiet {
+                eprintln!(
+                    "Skipping {:?}, which is the output of an earlier run",
+                    file_in
+                );
+            }
+            continue;
+        }
+        if cli.skip_existing && dir_out.join(job.output_name(&file_in, 1)?).exists() {
+            continue;
+        }
+        let file_out = job.output_path(&file_in, &dir_out, &mut claimed)?;
+        tasks.push((file_in, file_out));
+    }
+
+    let jobs = cli
+        .jobs
+        .or_else(|| thread::available_parallelism().map(usize::from).ok())
+        .unwrap_or(1);
+    let failures = sort_batch(&job, tasks, jobs, &*progress);
+
+    if failures.is_empty() {
+        Ok(())
+    } else {
+        eprintln!("{} file(s) could not be sorted:", failures.len());
+        for (file_in, e) in &failures {
+            eprintln!("  {:?}: {}", file_in, e);
+        }
+        Err(format!("{} of the files failed", failures.len()).into())
+    }
+}
+
+/// List the image files at a path along with their directory relative to it,
+/// descending into subdirectories if `recursive` is set.
+fn find_images(path: &Path, recursive: bool) -> Result<Vec<(PathBuf, PathBuf)>, Box<dyn Error>> {
+    if !path.is_dir() {
+        return Ok(vec![(path.to_owned(), PathBuf::new())]);
+    }
+
+    let mut entries = fs::read_dir(path)?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<Result<Vec<_>, _>>()?;
+    entries.sort();
+
+    let mut images = Vec::new();
+    for entry in entries {
+        if entry.is_dir() {
+            if recursive {
+                let subdir = PathBuf::from(entry.file_name().unwrap_or_default());
+                images.extend(
+                    find_images(&entry, true)?
+                        .into_iter()
+                        .map(|(file, rel)| (file, subdir.join(rel))),
+                );
+            }
+        } else if input_format(&entry).ok().flatten().is_some() {
+            images.push((entry, PathBuf::new()));
+        }
+    }
+
+    Ok(images)
+}
+
+/// Sort each file on one of `jobs` threads, returning the files that failed
+/// along with why. Progress is reported per file rather than per path.
+fn sort_batch(
+    job: &Job,
+    tasks: Vec<(PathBuf, PathBuf)>,
+    jobs: usize,
+    progress: &dyn Progress,
+) -> Vec<(PathBuf, String)> {
+    progress.start("Sorting files", tasks.len() as u64);
+
+    let queue = Mutex::new(tasks.into_iter());
+    let (tx, rx) = mpsc::channel();
+
+    let mut failures = thread::scope(|s| {
+        for _ in 0..jobs.max(1) {
+            let (queue, tx) = (&queue, tx.clone());
+            s.spawn(move || loop {
+                let task = queue.lock().map(|mut q| q.next());
+                let (file_in, file_out) = match task {
+                    Ok(Some(task)) => task,
+                    _ => break,
+                };
+
+                let result = file_out
+                    .parent()
+                    .map_or(Ok(()), fs::create_dir_all)
+                    .map_err(Into::into)
+                    .and_then(|()| job.sort_file(&file_in, &file_out, &()))
+                    .map_err(|e| e.to_string());
+                if tx.send((file_in, result)).is_err() {
+                    break;
+                }
+            });
+        }
+        drop(tx);
+
+        rx.iter()
+            .filter_map(|(file_in, result)| {
+                progress.inc(1);
+                result.err().map(|e| (file_in, e))
+            })
+            .collect::<Vec<_>>()
+    });
+
+    progress.finish();
+    failures.sort();
+    failures
+}
+
+/// Whether two paths lead to the same file, which need not exist yet.
+fn same_file(a: &Path, b: &Path) -> bool {
+    let resolve = |p: &Path| {
+        p.canonicalize().ok().or_else(|| {
+            let dir = p
+                .parent()
+                .filter(|d| !d.as_os_str().is_empty())
+                .unwrap_or_else(|| Path::new("."));
+            Some(dir.canonicalize().ok()?.join(p.file_name()?))
+        })
     };
 
-    eprintln!("Saving file to {:?}", file_out);
-    img_out.save(file_out)?;
+    match (resolve(a), resolve(b)) {
+        (Some(a), Some(b)) => a == b,
+        _ => false,
+    }
+}
+
+/// Whether a path stands for stdin or stdout.
+fn is_stdio(path: &Path) -> bool {
+    path == Path::new("-")
+}
 
+/// Read a whole input file, where `-` is stdin.
+fn read_input(path: &Path) -> io::Result<Vec<u8>> {
+    let mut data = Vec::new();
+    if is_stdio(path) {
+        io::stdin().read_to_end(&mut data)?;
+    } else {
+        File::open(path)?.read_to_end(&mut data)?;
+    }
+    Ok(data)
+}
+
+/// The format to save `path` in: `format` if given, otherwise the one its
+/// extension implies.
+fn output_format(path: &Path, format: Option<ImageFormat>) -> Option<ImageFormat> {
+    format.or_else(|| {
+        path.extension()
+            .and_then(OsStr::to_str)
+            .and_then(|e| parse_format(e).ok())
+    })
+}
+
+/// Save an image in `format`, or the one its extension implies, along with
+/// the input's metadata.
+fn write_image(
+    img: &DynamicImage,
+    path: &Path,
+    format: Option<ImageFormat>,
+    save: &SaveOptions,
+    meta: &Metadata,
+) -> Result<(), Box<dyn Error>> {
+    match output_format(path, format) {
+        Some(ImageFormat::HDR) => Err("8-bit images cannot be saved as HDR".into()),
+        Some(f) => {
+            let mut data = Vec::new();
+            write_with_options(img, &mut data, f, save)?;
+            write_embedded(path, data, meta)
+        }
+        None => Ok(save_with_options(img, path, save)?),
+    }
+}
+
+/// Write encoded image data with the input's metadata inserted.
+fn write_embedded(path: &Path, data: Vec<u8>, meta: &Metadata) -> Result<(), Box<dyn Error>> {
+    let mut w = create(path)?;
+    w.write_all(&meta.embed(data))?;
+    w.flush()?;
     Ok(())
 }
+
+/// Create an output file, where `-` is stdout.
+fn create(path: &Path) -> io::Result<Box<dyn Write>> {
+    if is_stdio(path) {
+        Ok(Box::new(io::stdout()))
+    } else {
+        Ok(Box::new(BufWriter::new(File::create(path)?)))
+    }
+}
+
+#[allow(clippy::needless_pass_by_value)]
+fn check_quality(quality: String) -> Result<(), String> {
+    match quality.parse::<u8>() {
+        Ok(1..=100) => Ok(()),
+        _ => Err("Quality must be a whole number from 1 to 100".to_string()),
+    }
+}
+
+/// Parse the name of an output format.
+fn parse_format(s: &str) -> Result<ImageFormat, String> {
+    match s.to_ascii_lowercase().as_str() {
+        "png" => Ok(ImageFormat::PNG),
+        "jpeg" | "jpg" => Ok(ImageFormat::JPEG),
+        "gif" => Ok(ImageFormat::GIF),
+        "bmp" => Ok(ImageFormat::BMP),
+        "ico" => Ok(ImageFormat::ICO),
+        "pnm" => Ok(ImageFormat::PNM),
+        "hdr" => Ok(ImageFormat::HDR),
+        _ => Err(format!("Unknown output format `{}`", s)),
+    }
+}
+
+/// The usual file extension for an output format.
+fn format_extension(format: ImageFormat) -> &'static str {
+    match format {
+        ImageFormat::JPEG => "jpg",
+        ImageFormat::GIF => "gif",
+        ImageFormat::BMP => "bmp",
+        ImageFormat::ICO => "ico",
+        ImageFormat::PNM => "pnm",
+        ImageFormat::HDR => "hdr",
+        _ => "png",
+    }
+}
+
+/// The format implied by a file's extension, for formats that cannot be
+/// recognised by their contents.
+fn extension_format(path: &Path) -> Option<ImageFormat> {
+    match path.extension()?.to_str()?.to_ascii_lowercase().as_str() {
+        "tga" => Some(ImageFormat::TGA),
+        "tif" | "tiff" => Some(ImageFormat::TIFF),
+        "pbm" | "pam" | "ppm" | "pgm" => Some(ImageFormat::PNM),
+        _ => None,
+    }
+}
+
+/// Whether an image can be saved in the format its extension names.
+fn is_writable(path: &Path) -> bool {
+    let ext = path.extension().and_then(OsStr::to_str).unwrap_or_default();
+    parse_format(ext).is_ok() || extension_format(path) == Some(ImageFormat::PNM)
+}
+
+/// Guess the format of an image file from its first few bytes, or from its
+/// extension if they are not recognised.
+fn input_format(path: &Path) -> Result<Option<ImageFormat>, Box<dyn Error>> {
+    let mut magic = Vec::new();
+    File::open(path)?.take(64).read_to_end(&mut magic)?;
+    Ok(image::guess_format(&magic)
+        .ok()
+        .or_else(|| extension_format(path)))
+}
+
+/// Whether a PNG has an animation control chunk, which must come before its
+/// image data. The decoder ignores the animation and reads only the still image.
+fn is_apng(data: &[u8]) -> bool {
+    let mut pos = 8;
+    while let Some(chunk) = data.get(pos..pos + 8) {
+        match &chunk[4..] {
+            b"acTL" => return true,
+            b"IDAT" | b"IEND" => return false,
+            _ => (),
+        }
+        let len = u32::from_be_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+        pos = pos.saturating_add(12 + len as usize);
+    }
+    false
+}
+
+/// Sort every image in a directory in frame number order, writing each to a
+/// file of the same name in `dir_out`.
+fn sort_sequence(
+    dir_in: &Path,
+    dir_out: &Path,
+    mut sequence: Sequence,
+    save: &SaveOptions,
+    progress: &dyn Progress,
+    force: bool,
+    quiet: bool,
+) -> Result<(), Box<dyn Error>> {
+    if save.format == Some(ImageFormat::HDR) {
+        return Err("Sequences are sorted at 8 bits and cannot be saved as HDR".into());
+    }
+
+    let mut frames = fs::read_dir(dir_in)?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<Result<Vec<_>, _>>()?;
+    frames.retain(|p| p.is_file() && input_format(p).ok().flatten().is_some());
+    frames.sort();
+    frames.sort_by_key(|p| frame_key(p));
+
+    // every output is checked before any is written, so a refused run leaves
+    // the directory as it was
+    let files_out = frames
+        .iter()
+        .map(|frame| Ok(dir_out.join(frame.file_name().ok_or("Invalid frame name")?)))
+        .collect::<Result<Vec<_>, Box<dyn Error>>>()?;
+    for (frame, file_out) in frames.iter().zip(&files_out) {
+        if same_file(frame, file_out) {
+            return Err(format!("{:?} is both the input and the output", frame).into());
+        }
+        if file_out.exists() && !force {
+            return Err(format!("{:?} already exists; use --force to overwrite", file_out).into());
+        }
+    }
+
+    if !quiet {
+        eprintln!(
+            "Sorting {} frames from {:?} into {:?}",
+            frames.len(),
+            dir_in,
+            dir_out
+        );
+    }
+    fs::create_dir_all(dir_out)?;
+
+    progress.start("Sorting frames", frames.len() as u64);
+    for (frame, file_out) in frames.iter().zip(files_out) {
+        let data = read_input(frame)?;
+        let mut meta = Metadata::read(&data);
+        let img = match image::guess_format(&data)
+            .ok()
+            .or_else(|| extension_format(frame))
+        {
+            Some(f) => image::load_from_memory_with_format(&data, f),
+            None => image::load_from_memory(&data),
+        }?;
+        let img_out = sequence.sort_frame(meta.apply_orientation(img));
+        write_image(&img_out, &file_out, save.format, save, &meta)?;
+        progress.inc(1);
+    }
+    progress.finish();
+
+    Ok(())
+}
+
+/// A sort key that orders the numbers in a frame's name by value, so `f2`
+/// comes before `f10` even without zero padding.
+fn frame_key(path: &Path) -> Vec<(String, u64)> {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let mut key = Vec::new();
+    let (mut text, mut digits) = (String::new(), String::new());
+    for c in name.chars() {
+        if c.is_ascii_digit() {
+            digits.push(c);
+            continue;
+        }
+        if !digits.is_empty() {
+            let number = digits.parse().unwrap_or(u64::MAX);
+            key.push((std::mem::take(&mut text), number));
+            digits.clear();
+        }
+        text.push(c);
+    }
+    key.push((text, digits.parse().unwrap_or(0)));
+    key
+}
+
+/// Insert a zero-padded frame number before a path's extension.
+fn numbered(path: &Path, idx: usize, digits: usize) -> PathBuf {
+    let mut fname = path.file_stem().unwrap_or_default().to_owned();
+    fname.push(format!("_{:0width$}", idx, width = digits));
+    if let Some(e) = path.extension() {
+        fname.push(".");
+        fname.push(e);
+    }
+    path.with_file_name(fname)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn animated_png() {
+        let mut png = Vec::new();
+        image::png::PNGEncoder::new(&mut png)
+            .encode(&[0; 12], 2, 2, image::ColorType::RGB(8))
+            .expect("encode test image");
+        assert!(!is_apng(&png));
+
+        // the control chunk goes straight after the 25 byte header chunk
+        let mut apng = png.clone();
+        let actl = b"\0\0\0\x08acTL\0\0\0\x02\0\0\0\0\0\0\0\0";
+        apng.splice(33..33, actl.iter().copied());
+        assert!(is_apng(&apng));
+
+        // after the image data it is not part of a valid animation
+        let idat = png.len() - 12;
+        let mut late = png;
+        late.splice(idat..idat, actl.iter().copied());
+        assert!(!is_apng(&late));
+        assert!(!is_apng(&apng[..20]));
+    }
+
+    #[test]
+    fn frame_order() {
+        let mut frames = [
+            "f10.png", "f2.png", "f1.png", "g1.png", "f02.png", "f1b.png",
+        ]
+        .iter()
+        .map(PathBuf::from)
+        .collect::<Vec<_>>();
+        frames.sort();
+        frames.sort_by_key(|p| frame_key(p));
+        assert_eq!(
+            frames,
+            ["f1.png", "f1b.png", "f02.png", "f2.png", "f10.png", "g1.png"]
+                .iter()
+                .map(PathBuf::from)
+                .collect::<Vec<_>>()
+        );
+    }
+}
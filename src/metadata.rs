@@ -0,0 +1,498 @@
+use image::imageops::{flip_horizontal, flip_vertical, rotate180, rotate270, rotate90};
+use image::{DynamicImage, ImageBuffer, Pixel};
+
+use crate::DeepImage;
+
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+const EXIF_HEADER: &[u8] = b"Exif\0\0";
+const ICC_HEADER: &[u8] = b"ICC_PROFILE\0";
+const ORIENTATION_TAG: u16 = 0x0112;
+
+/// EXIF and ICC metadata carried from an input file to the sorted output.
+///
+/// Metadata is read from JPEG, PNG and TIFF files, and written to JPEG and
+/// PNG files. Only the orientation is read from a TIFF file, as its other
+/// tags can point into its image data.
+#[derive(Clone, Debug, Default)]
+pub struct Metadata {
+    /// EXIF data as a TIFF structure, without the `Exif` header used in JPEG files.
+    pub exif: Option<Vec<u8>>,
+    /// ICC colour profile.
+    pub icc: Option<Vec<u8>>,
+}
+
+impl Metadata {
+    /// Read metadata from an encoded JPEG, PNG or TIFF. Any other data, or
+    /// metadata that cannot be parsed, yields empty metadata.
+    #[must_use]
+    pub fn read(data: &[u8]) -> Self {
+        if data.starts_with(b"\xff\xd8") {
+            read_jpeg(data)
+        } else if data.starts_with(PNG_SIGNATURE) {
+            read_png(data)
+        } else if is_tiff(data) {
+            read_tiff(data)
+        } else {
+            Self::default()
+        }
+    }
+
+    /// The EXIF orientation, from 1 to 8, if there is one.
+    #[must_use]
+    pub fn orientation(&self) -> Option<u16> {
+        let exif = self.exif.as_ref()?;
+        orientation_offset(exif).map(|offset| read_u16(exif, offset, exif[0] == b'I'))
+    }
+
+    /// Rotate and flip an image so it appears the way viewers display it, and
+    /// reset the orientation to match.
+    pub fn apply_orientation(&mut self, img: DynamicImage) -> DynamicImage {
+        let img = match self.orientation() {
+            Some(2) => img.fliph(),
+            Some(3) => img.rotate180(),
+            Some(4) => img.flipv(),
+            Some(5) => img.rotate90().fliph(),
+            Some(6) => img.rotate90(),
+            Some(7) => img.rotate270().fliph(),
+            Some(8) => img.rotate270(),
+            _ => return img,
+        };
+
+        self.reset_orientation();
+        img
+    }
+
+    /// Rotate and flip an image deeper than 8 bits like
+    /// [`apply_orientation`](#method.apply_orientation).
+    pub fn apply_deep_orientation(&mut self, img: DeepImage) -> DeepImage {
+        let orientation = self.orientation();
+        self.reset_orientation();
+
+        match img {
+            DeepImage::Rgba16(buffer, color) => {
+                DeepImage::Rgba16(orient(buffer, orientation), color)
+            }
+            DeepImage::RgbaF32(buffer) => DeepImage::RgbaF32(orient(buffer, orientation)),
+        }
+    }
+
+    fn reset_orientation(&mut self) {
+        if let Some(exif) = &mut self.exif {
+            if let Some(offset) = orientation_offset(exif) {
+                let bytes = if exif[0] == b'I' { [1, 0] } else { [0, 1] };
+                exif[offset..offset + 2].copy_from_slice(&bytes);
+            }
+        }
+    }
+
+    /// Insert the metadata into an encoded JPEG or PNG. Other data is
+    /// returned unchanged.
+    #[must_use]
+    pub fn embed(&self, data: Vec<u8>) -> Vec<u8> {
+        if self.exif.is_none() && self.icc.is_none() {
+            data
+        } else if data.starts_with(b"\xff\xd8") {
+            self.embed_jpeg(&data)
+        } else if data.starts_with(PNG_SIGNATURE) {
+            self.embed_png(&data)
+        } else {
+            data
+        }
+    }
+
+    #[allow(clippy::cast_possible_truncation)]
+    fn embed_jpeg(&self, data: &[u8]) -> Vec<u8> {
+        // segments go after the start of image and any JFIF header
+        let mut pos = 2;
+        if data[pos..].starts_with(b"\xff\xe0") {
+            pos += 2 + usize::from(read_u16(data, pos + 2, false));
+        }
+
+        let mut out = data[..pos].to_vec();
+        if let Some(exif) = &self.exif {
+            let mut payload = EXIF_HEADER.to_vec();
+            payload.extend_from_slice(exif);
+            push_segment(&mut out, 0xe1, &payload);
+        }
+        // profiles are split into at most 255 numbered chunks that each fit a segment
+        let chunks = self.icc.as_ref().map_or_else(Vec::new, |icc| {
+            icc.chunks(0xffff - 2 - ICC_HEADER.len() - 2)
+                .collect::<Vec<_>>()
+        });
+        if chunks.len() <= 255 {
+            for (idx, chunk) in chunks.iter().enumerate() {
+                let mut payload = ICC_HEADER.to_vec();
+                payload.extend_from_slice(&[idx as u8 + 1, chunks.len() as u8]);
+                payload.extend_from_slice(chunk);
+                push_segment(&mut out, 0xe2, &payload);
+            }
+        }
+        out.extend_from_slice(&data[pos..]);
+
+        out
+    }
+
+    fn embed_png(&self, data: &[u8]) -> Vec<u8> {
+        // chunks go after the header chunk, which always comes first
+        let pos = PNG_SIGNATURE.len() + 12 + read_u32(data, PNG_SIGNATURE.len()) as usize;
+
+        let mut out = data[..pos].to_vec();
+        if let Some(icc) = &self.icc {
+            let mut payload = b"ICC Profile\0\0".to_vec();
+            payload.extend(deflate::deflate_bytes_zlib(icc));
+            push_chunk(&mut out, *b"iCCP", &payload);
+        }
+        if let Some(exif) = &self.exif {
+            push_chunk(&mut out, *b"eXIf", exif);
+        }
+        out.extend_from_slice(&data[pos..]);
+
+        out
+    }
+}
+
+/// Rotate and flip a buffer the way an EXIF orientation asks.
+fn orient<P>(
+    img: ImageBuffer<P, Vec<P::Subpixel>>,
+    orientation: Option<u16>,
+) -> ImageBuffer<P, Vec<P::Subpixel>>
+where
+    P: Pixel + 'static,
+    P::Subpixel: 'static,
+{
+    match orientation {
+        Some(2) => flip_horizontal(&img),
+        Some(3) => rotate180(&img),
+        Some(4) => flip_vertical(&img),
+        Some(5) => flip_horizontal(&rotate90(&img)),
+        Some(6) => rotate90(&img),
+        Some(7) => flip_horizontal(&rotate270(&img)),
+        Some(8) => rotate270(&img),
+        _ => img,
+    }
+}
+
+fn read_jpeg(data: &[u8]) -> Metadata {
+    let mut meta = Metadata::default();
+    let mut icc_chunks = Vec::new();
+
+    let mut pos = 2;
+    while pos + 4 <= data.len() && data[pos] == 0xff {
+        let marker = data[pos + 1];
+        match marker {
+            // start of scan or end of image; no more metadata
+            0xd9 | 0xda => break,
+            // markers without a length
+            0x01 | 0xd0..=0xd7 => {
+                pos += 2;
+                continue;
+            }
+            _ => (),
+        }
+
+        let len = usize::from(read_u16(data, pos + 2, false));
+        let end = (pos + 2 + len).min(data.len());
+        let payload = &data[(pos + 4).min(end)..end];
+
+        if marker == 0xe1 && payload.starts_with(EXIF_HEADER) {
+            meta.exif = Some(payload[EXIF_HEADER.len()..].to_vec());
+        } else if marker == 0xe2 && payload.starts_with(ICC_HEADER) && payload.len() > 14 {
+            icc_chunks.push((payload[12], payload[14..].to_vec()));
+        }
+
+        pos = end;
+    }
+
+    if !icc_chunks.is_empty() {
+        icc_chunks.sort_by_key(|(seq, _)| *seq);
+        meta.icc = Some(icc_chunks.into_iter().flat_map(|(_, c)| c).collect());
+    }
+    meta.exif = meta.exif.filter(|e| is_tiff(e));
+
+    meta
+}
+
+fn read_png(data: &[u8]) -> Metadata {
+    let mut meta = Metadata::default();
+
+    let mut pos = PNG_SIGNATURE.len();
+    while pos + 12 <= data.len() {
+        let len = read_u32(data, pos) as usize;
+        let kind = &data[pos + 4..pos + 8];
+        let end = (pos + 8 + len).min(data.len());
+        let payload = &data[pos + 8..end];
+
+        match kind {
+            b"eXIf" => meta.exif = Some(payload.to_vec()).filter(|e| is_tiff(e)),
+            b"iCCP" => {
+                // profile name, then a compression method byte, then the profile
+                meta.icc = payload
+                    .iter()
+                    .position(|&b| b == 0)
+                    .and_then(|name| payload.get(name + 2..))
+                    .and_then(|zlib| inflate::inflate_bytes_zlib(zlib).ok());
+            }
+            b"IEND" => break,
+            _ => (),
+        }
+
+        pos = end + 4;
+    }
+
+    meta
+}
+
+fn read_tiff(data: &[u8]) -> Metadata {
+    let le = data[0] == b'I';
+    Metadata {
+        exif: orientation_offset(data)
+            .map(|offset| orientation_exif(le, read_u16(data, offset, le))),
+        icc: None,
+    }
+}
+
+/// EXIF data holding only an orientation tag.
+#[allow(clippy::cast_possible_truncation)]
+fn orientation_exif(le: bool, orientation: u16) -> Vec<u8> {
+    // first IFD offset, one entry of tag, type SHORT, count and padded
+    // value, then no next IFD
+    let fields = [
+        (8, 4),
+        (1, 2),
+        (u32::from(ORIENTATION_TAG), 2),
+        (3, 2),
+        (1, 4),
+        (u32::from(orientation), 2),
+        (0, 2),
+        (0, 4),
+    ];
+
+    let mut exif = if le {
+        b"II*\0".to_vec()
+    } else {
+        b"MM\0*".to_vec()
+    };
+    for &(value, len) in &fields {
+        let bytes = &value.to_be_bytes()[4 - len..];
+        if le {
+            exif.extend(bytes.iter().rev());
+        } else {
+            exif.extend_from_slice(bytes);
+        }
+    }
+    exif
+}
+
+/// Whether EXIF data starts with a valid TIFF header.
+fn is_tiff(exif: &[u8]) -> bool {
+    exif.starts_with(b"II*\0") || exif.starts_with(b"MM\0*")
+}
+
+/// Find the value of the orientation tag in the first IFD of EXIF data.
+fn orientation_offset(exif: &[u8]) -> Option<usize> {
+    if !is_tiff(exif) || exif.len() < 8 {
+        return None;
+    }
+    let le = exif[0] == b'I';
+
+    let ifd = read_u32_endian(exif, 4, le) as usize;
+    if ifd + 2 > exif.len() {
+        return None;
+    }
+
+    (0..usize::from(read_u16(exif, ifd, le)))
+        .map(|idx| ifd + 2 + idx * 12)
+        .take_while(|&entry| entry + 12 <= exif.len())
+        .find(|&entry| read_u16(exif, entry, le) == ORIENTATION_TAG)
+        .map(|entry| entry + 8)
+}
+
+fn read_u16(data: &[u8], pos: usize, le: bool) -> u16 {
+    let bytes = [data[pos], data[pos + 1]];
+    if le {
+        u16::from_le_bytes(bytes)
+    } else {
+        u16::from_be_bytes(bytes)
+    }
+}
+
+fn read_u32_endian(data: &[u8], pos: usize, le: bool) -> u32 {
+    let bytes = [data[pos], data[pos + 1], data[pos + 2], data[pos + 3]];
+    if le {
+        u32::from_le_bytes(bytes)
+    } else {
+        u32::from_be_bytes(bytes)
+    }
+}
+
+fn read_u32(data: &[u8], pos: usize) -> u32 {
+    read_u32_endian(data, pos, false)
+}
+
+#[allow(clippy::cast_possible_truncation)]
+fn push_segment(out: &mut Vec<u8>, marker: u8, payload: &[u8]) {
+    if payload.len() + 2 > 0xffff {
+        return;
+    }
+    out.extend_from_slice(&[0xff, marker]);
+    out.extend_from_slice(&(payload.len() as u16 + 2).to_be_bytes());
+    out.extend_from_slice(payload);
+}
+
+#[allow(clippy::cast_possible_truncation)]
+fn push_chunk(out: &mut Vec<u8>, kind: [u8; 4], payload: &[u8]) {
+    out.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+    let start = out.len();
+    out.extend_from_slice(&kind);
+    out.extend_from_slice(payload);
+    let crc = crc32(&out[start..]);
+    out.extend_from_slice(&crc.to_be_bytes());
+}
+
+/// The CRC-32 checksum used by PNG chunks.
+fn crc32(data: &[u8]) -> u32 {
+    !data.iter().fold(!0, |crc, &byte| {
+        (0..8).fold(crc ^ u32::from(byte), |crc, _| {
+            if crc & 1 == 1 {
+                (crc >> 1) ^ 0xedb8_8320
+            } else {
+                crc >> 1
+            }
+        })
+    })
+}
+
+#[cfg(test)]
+#[allow(clippy::cast_possible_truncation)]
+mod tests {
+    use super::*;
+    use image::{ImageOutputFormat, Rgba, RgbaImage};
+
+    fn encode(format: ImageOutputFormat) -> Vec<u8> {
+        let img = RgbaImage::from_fn(6, 4, |x, y| Rgba([x as u8 * 40, y as u8 * 60, 0, 255]));
+        let mut data = Vec::new();
+        DynamicImage::ImageRgba8(img)
+            .write_to(&mut data, format)
+            .expect("encode test image");
+        data
+    }
+
+    fn metadata(icc_len: usize) -> Metadata {
+        Metadata {
+            exif: Some(orientation_exif(true, 6)),
+            icc: Some((0..icc_len).map(|i| (i % 251) as u8).collect()),
+        }
+    }
+
+    #[test]
+    fn orientation() {
+        for &le in &[true, false] {
+            for orientation in 1..=8 {
+                let meta = Metadata {
+                    exif: Some(orientation_exif(le, orientation)),
+                    icc: None,
+                };
+                assert_eq!(meta.orientation(), Some(orientation));
+            }
+        }
+
+        let meta = Metadata {
+            exif: Some(b"not exif".to_vec()),
+            icc: None,
+        };
+        assert_eq!(meta.orientation(), None);
+    }
+
+    #[test]
+    fn apply_orientation() {
+        let mut meta = Metadata {
+            exif: Some(orientation_exif(false, 6)),
+            icc: None,
+        };
+        let img = meta.apply_orientation(DynamicImage::new_rgba8(6, 4));
+        assert_eq!(img.to_rgba().dimensions(), (4, 6));
+        assert_eq!(meta.orientation(), Some(1));
+
+        let mut meta = Metadata {
+            exif: Some(orientation_exif(true, 8)),
+            icc: None,
+        };
+        let img = meta.apply_deep_orientation(DeepImage::RgbaF32(ImageBuffer::new(6, 4)));
+        match img {
+            DeepImage::RgbaF32(buffer) => assert_eq!(buffer.dimensions(), (4, 6)),
+            DeepImage::Rgba16(..) => panic!("depth changed"),
+        }
+        assert_eq!(meta.orientation(), Some(1));
+    }
+
+    #[test]
+    fn jpeg_round_trip() {
+        // a profile too large for one segment is split across several
+        for &icc_len in &[100, 150_000] {
+            let meta = metadata(icc_len);
+            let data = meta.embed(encode(ImageOutputFormat::JPEG(90)));
+            image::load_from_memory(&data).expect("still a valid JPEG");
+
+            let read = Metadata::read(&data);
+            assert_eq!(read.exif, meta.exif);
+            assert_eq!(read.icc, meta.icc);
+        }
+    }
+
+    #[test]
+    fn png_round_trip() {
+        let meta = metadata(5000);
+        let data = meta.embed(encode(ImageOutputFormat::PNG));
+        // the decoder checks every chunk's CRC
+        image::load_from_memory(&data).expect("still a valid PNG");
+
+        let read = Metadata::read(&data);
+        assert_eq!(read.exif, meta.exif);
+        assert_eq!(read.icc, meta.icc);
+    }
+
+    #[test]
+    fn tiff_orientation() {
+        for &le in &[true, false] {
+            // the whole file is a TIFF structure, but only the orientation is kept
+            let mut tiff = orientation_exif(le, 6);
+            tiff.extend_from_slice(&[0x55; 64]);
+            let mut read = Metadata::read(&tiff);
+            assert_eq!(read.exif, Some(orientation_exif(le, 6)));
+
+            let img = read.apply_orientation(DynamicImage::new_rgba8(6, 4));
+            assert_eq!(img.to_rgba().dimensions(), (4, 6));
+            let data = read.embed(encode(ImageOutputFormat::PNG));
+            assert_eq!(Metadata::read(&data).orientation(), Some(1));
+
+            for len in 0..tiff.len() {
+                let _ = Metadata::read(&tiff[..len]).orientation();
+            }
+        }
+
+        // a TIFF without an orientation has nothing to carry
+        let tiff = b"II*\0\x08\0\0\0\0\0\0\0\0\0";
+        assert!(Metadata::read(tiff).exif.is_none());
+    }
+
+    #[test]
+    fn no_metadata() {
+        for format in &[ImageOutputFormat::PNG, ImageOutputFormat::JPEG(90)] {
+            let data = encode(format.clone());
+            let read = Metadata::read(&data);
+            assert!(read.exif.is_none() && read.icc.is_none());
+            assert_eq!(read.embed(data.clone()), data);
+        }
+        assert!(Metadata::read(b"GIF89a").exif.is_none());
+    }
+
+    #[test]
+    fn truncated() {
+        for format in &[ImageOutputFormat::PNG, ImageOutputFormat::JPEG(90)] {
+            let data = metadata(300).embed(encode(format.clone()));
+            for len in 0..data.len() {
+                let _ = Metadata::read(&data[..len]).orientation();
+            }
+        }
+    }
+}
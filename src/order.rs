@@ -0,0 +1,288 @@
+use std::cmp::Ordering;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::path::unwrap_parens;
+use crate::rng::Rng;
+use crate::Channel;
+
+/// How the pixels of each span are arranged.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Order {
+    /// Sorted from lowest to highest.
+    Ascending,
+    /// Rising to the highest value in the middle, then falling again.
+    Mountain,
+    /// Falling to the lowest value in the middle, then rising again.
+    Valley,
+    /// Sorted in alternating directions from one span to the next.
+    Alternate,
+    /// Shuffled randomly, reproducibly.
+    Shuffle,
+    /// Left unsorted, but reversed.
+    Reverse,
+    /// Left unsorted, but rotated along the span by this many pixels.
+    Rotate(isize),
+}
+
+impl Order {
+    /// Whether the span numbered `span` along its path is sorted against
+    /// the configured direction.
+    pub(crate) fn flips(self, span: usize) -> bool {
+        self == Order::Alternate && span % 2 == 1
+    }
+}
+
+/// Settings for arranging the spans along one path.
+pub(crate) struct Arrangement<'a> {
+    pub(crate) order: Order,
+    /// Flip the direction of the orders that sort, so mountains become
+    /// valleys and valleys mountains.
+    pub(crate) descending: bool,
+    /// Below 1, each item only moves this fraction of the way from where it
+    /// is to where it would be arranged.
+    pub(crate) strength: f32,
+    /// Keep equal items in their original order.
+    pub(crate) stable: bool,
+    pub(crate) rng: &'a mut Rng,
+}
+
+impl Arrangement<'_> {
+    /// Arrange a span.
+    #[allow(clippy::cast_precision_loss)]
+    pub(crate) fn arrange<X, F>(&mut self, items: &mut [X], compare: F)
+    where
+        X: Copy,
+        F: Fn(&X, &X) -> Ordering,
+    {
+        if self.strength >= 1. {
+            return self.arrange_fully(items, compare);
+        }
+
+        // arrange the indices to find where each item would go
+        let mut targets = (0..items.len()).collect::<Vec<_>>();
+        self.arrange_fully(&mut targets, |l, r| compare(&items[*l], &items[*r]));
+        let mut moved = vec![0.; items.len()];
+        for (target, &idx) in targets.iter().enumerate() {
+            moved[idx] = idx as f32 + (target as f32 - idx as f32) * self.strength;
+        }
+
+        let mut positions = (0..items.len()).collect::<Vec<_>>();
+        positions.sort_by(|l, r| moved[*l].compare(&moved[*r]).then(l.cmp(r)));
+        let original = items.to_vec();
+        for (item, idx) in items.iter_mut().zip(positions) {
+            *item = original[idx];
+        }
+    }
+
+    fn arrange_fully<X, F>(&mut self, items: &mut [X], compare: F)
+    where
+        X: Copy,
+        F: Fn(&X, &X) -> Ordering,
+    {
+        let stable = self.stable;
+        let sort = |items: &mut [X], descending: bool| {
+            let compare = |l: &X, r: &X| {
+                if descending {
+                    compare(r, l)
+                } else {
+                    compare(l, r)
+                }
+            };
+            if stable {
+                items.sort_by(compare);
+            } else {
+                items.sort_unstable_by(compare);
+            }
+        };
+
+        match self.order {
+            Order::Ascending | Order::Alternate => sort(items, self.descending),
+            Order::Mountain | Order::Valley => {
+                sort(items, self.descending != (self.order == Order::Valley));
+
+                // every other value climbs to the middle, the rest come back down
+                let sorted = items.to_vec();
+                let len = items.len();
+                for (idx, x) in sorted.into_iter().enumerate() {
+                    items[if idx % 2 == 0 {
+                        idx / 2
+                    } else {
+                        len - 1 - idx / 2
+                    }] = x;
+                }
+            }
+            Order::Shuffle => {
+                for idx in (1..items.len()).rev() {
+                    items.swap(idx, self.rng.below(idx + 1));
+                }
+            }
+            Order::Reverse => items.reverse(),
+            Order::Rotate(k) => {
+                if !items.is_empty() {
+                    // negative rotations turn the other way
+                    let shift = k.unsigned_abs() % items.len();
+                    if k < 0 {
+                        items.rotate_left(shift);
+                    } else {
+                        items.rotate_right(shift);
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl FromStr for Order {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "" | "ascending" => Ok(Order::Ascending),
+            "mountain" => Ok(Order::Mountain),
+            "valley" => Ok(Order::Valley),
+            "alternate" => Ok(Order::Alternate),
+            "shuffle" => Ok(Order::Shuffle),
+            "reverse" => Ok(Order::Reverse),
+            "rotate" => Ok(Order::Rotate(1)),
+            st => {
+                let err_msg = format!("Could not parse `{}` as a valid order", st);
+
+                st.strip_prefix("rotate")
+                    .and_then(|args| unwrap_parens(args).ok())
+                    .and_then(|k| k.trim().parse().ok())
+                    .map(Order::Rotate)
+                    .ok_or(err_msg)
+            }
+        }
+    }
+}
+
+impl fmt::Display for Order {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Order::Ascending => write!(f, "ascending"),
+            Order::Mountain => write!(f, "mountain"),
+            Order::Valley => write!(f, "valley"),
+            Order::Alternate => write!(f, "alternate"),
+            Order::Shuffle => write!(f, "shuffle"),
+            Order::Reverse => write!(f, "reverse"),
+            Order::Rotate(k) => write!(f, "rotate({})", k),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn arranged(order: Order, descending: bool, items: &[u8]) -> Vec<u8> {
+        partly_arranged(order, descending, 1., items)
+    }
+
+    fn partly_arranged(order: Order, descending: bool, strength: f32, items: &[u8]) -> Vec<u8> {
+        let mut rng = Rng::new(0);
+        let mut arrangement = Arrangement {
+            order,
+            descending,
+            strength,
+            stable: true,
+            rng: &mut rng,
+        };
+        let mut items = items.to_vec();
+        arrangement.arrange(&mut items, u8::cmp);
+        items
+    }
+
+    #[test]
+    fn mountain_and_valley() {
+        let items = [5, 1, 4, 2, 3];
+        assert_eq!(arranged(Order::Mountain, false, &items), [1, 3, 5, 4, 2]);
+        assert_eq!(arranged(Order::Valley, false, &items), [5, 3, 1, 2, 4]);
+        assert_eq!(arranged(Order::Mountain, true, &items), [5, 3, 1, 2, 4]);
+        assert_eq!(arranged(Order::Valley, true, &items), [1, 3, 5, 4, 2]);
+        assert_eq!(
+            arranged(Order::Mountain, false, &[2, 1, 4, 3]),
+            [1, 3, 4, 2]
+        );
+    }
+
+    #[test]
+    fn rotate() {
+        let items = [1, 2, 3, 4, 5];
+        assert_eq!(arranged(Order::Rotate(1), false, &items), [5, 1, 2, 3, 4]);
+        assert_eq!(arranged(Order::Rotate(-1), false, &items), [2, 3, 4, 5, 1]);
+        assert_eq!(arranged(Order::Rotate(7), false, &items), [4, 5, 1, 2, 3]);
+        assert_eq!(arranged(Order::Rotate(-7), false, &items), [3, 4, 5, 1, 2]);
+        assert_eq!(arranged(Order::Rotate(5), false, &items), items);
+        assert_eq!(arranged(Order::Rotate(3), false, &[]), []);
+    }
+
+    #[test]
+    fn alternate_flips_every_other_span() {
+        let spans = (0..4)
+            .map(|span| arranged(Order::Alternate, Order::Alternate.flips(span), &[2, 3, 1]))
+            .collect::<Vec<_>>();
+        assert_eq!(spans, [[1, 2, 3], [3, 2, 1], [1, 2, 3], [3, 2, 1]]);
+
+        // reversing starts every span the other way
+        let reversed = (0..2)
+            .map(|span| arranged(Order::Alternate, !Order::Alternate.flips(span), &[2, 3, 1]))
+            .collect::<Vec<_>>();
+        assert_eq!(reversed, [[3, 2, 1], [1, 2, 3]]);
+
+        assert!(!Order::Ascending.flips(1));
+    }
+
+    #[test]
+    fn strength() {
+        let items = [4, 1, 2, 3];
+        assert_eq!(partly_arranged(Order::Ascending, false, 0., &items), items);
+        assert_eq!(partly_arranged(Order::Mountain, false, 0., &items), items);
+        assert_eq!(
+            partly_arranged(Order::Ascending, false, 1., &items),
+            [1, 2, 3, 4]
+        );
+
+        // only the partial branch interpolates, but close to 1 it must agree
+        assert_eq!(
+            partly_arranged(Order::Ascending, false, 0.99, &items),
+            [1, 2, 3, 4]
+        );
+        assert_eq!(
+            partly_arranged(Order::Mountain, true, 0.99, &items),
+            arranged(Order::Mountain, true, &items)
+        );
+
+        // halfway, 4 and 2 both land at 1.5 and keep their original order
+        assert_eq!(
+            partly_arranged(Order::Ascending, false, 0.5, &items),
+            [1, 4, 2, 3]
+        );
+        // reversed items all meet in the middle, so none has passed another yet
+        assert_eq!(
+            partly_arranged(Order::Reverse, false, 0.5, &[1, 2, 3, 4, 5]),
+            [1, 2, 3, 4, 5]
+        );
+        assert_eq!(
+            partly_arranged(Order::Rotate(2), false, 0.5, &[1, 2, 3, 4, 5]),
+            [1, 4, 2, 5, 3]
+        );
+    }
+
+    #[test]
+    fn parse() {
+        assert_eq!("".parse(), Ok(Order::Ascending));
+        assert_eq!(" valley ".parse(), Ok(Order::Valley));
+        assert_eq!("rotate".parse(), Ok(Order::Rotate(1)));
+        assert_eq!("rotate(-3)".parse(), Ok(Order::Rotate(-3)));
+        assert_eq!("rotate( 12 )".parse(), Ok(Order::Rotate(12)));
+        assert!("rotate(x)".parse::<Order>().is_err());
+        assert!("rotate(1".parse::<Order>().is_err());
+        assert!("sideways".parse::<Order>().is_err());
+
+        for order in &[Order::Mountain, Order::Shuffle, Order::Rotate(-3)] {
+            assert_eq!(order.to_string().parse(), Ok(*order));
+        }
+    }
+}
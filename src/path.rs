@@ -1,3 +1,4 @@
+use std::fmt;
 use std::str::FromStr;
 
 const DEFAULT_AMP: f32 = 25.0;
@@ -15,6 +16,7 @@ const DEFAULT_ELL: Shape = Shape::Ellipse {
 };
 
 /// Path to follow through an image.
+#[derive(Clone, Copy)]
 pub enum Shape {
     Linear,
     Sine {
@@ -36,7 +38,25 @@ impl Default for Shape {
     }
 }
 
-fn unwrap_parens(s: &str) -> Result<&str, ()> {
+impl fmt::Display for Shape {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Shape::Linear => write!(f, "line"),
+            Shape::Sine {
+                amplitude,
+                lambda,
+                offset,
+            } => write!(f, "sine({},{},{})", amplitude, lambda, offset),
+            Shape::Ellipse {
+                eccentricity,
+                center: (x, y),
+            } => write!(f, "ellipse({},{},{})", eccentricity, x, y),
+            Shape::__Nonexhaustive => unreachable!(),
+        }
+    }
+}
+
+pub(crate) fn unwrap_parens(s: &str) -> Result<&str, ()> {
     let st = s.trim();
 
     if st.starts_with('(') && st.ends_with(')')
@@ -0,0 +1,270 @@
+use std::fmt;
+use std::iter::FromIterator;
+use std::str::FromStr;
+
+use image::{DynamicImage, ImageBuffer, Rgba, RgbaImage};
+
+use crate::{
+    from_rgba, into_rgba, Animation, CancelToken, Cancelled, Channel, Config, DeepImage, Frame,
+    Progress, Sweep,
+};
+
+/// A sequence of sorting passes, applied one after another.
+///
+/// The image is decoded to RGBA once and the same buffer is handed from each
+/// pass to the next.
+///
+/// A pipeline can be written as text with one pass per line, each in the same
+/// syntax as the command line options. Blank lines and lines starting with `#`
+/// are ignored:
+///
+/// ```text
+/// # sort columns by luma, then a 30 degree line by hue
+/// --function=luma --vertical
+/// --function=hue --angle=30
+/// ```
+#[derive(Clone, Default)]
+pub struct Pipeline {
+    passes: Vec<Config>,
+}
+
+impl Pipeline {
+    /// Create an empty pipeline.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Append a pass to the end of the pipeline.
+    pub fn push(&mut self, pass: Config) {
+        self.passes.push(pass);
+    }
+
+    /// The passes in the order they are applied.
+    #[must_use]
+    pub fn passes(&self) -> &[Config] {
+        &self.passes
+    }
+
+    /// Use the same random seed for every pass.
+    pub fn set_seed(&mut self, seed: u64) {
+        for pass in &mut self.passes {
+            pass.seed = seed;
+        }
+    }
+
+    /// Whether the pipeline has no passes.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.passes.is_empty()
+    }
+
+    #[must_use]
+    /// Apply every pass in order and return a new image.
+    pub fn sort(&self, img: DynamicImage) -> DynamicImage {
+        self.sort_with_progress(img, &())
+    }
+
+    #[must_use]
+    /// Apply every pass in order, reporting each one as a separate stage.
+    pub fn sort_with_progress(&self, img: DynamicImage, progress: &dyn Progress) -> DynamicImage {
+        match self.try_sort(img, progress, &CancelToken::new()) {
+            Ok(img) => img,
+            Err(Cancelled) => unreachable!("no other handle to the token exists"),
+        }
+    }
+
+    /// Apply every pass in order, stopping early if `cancel` is triggered.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err(Cancelled)` if the token was cancelled before the last pass finished.
+    pub fn try_sort(
+        &self,
+        img: DynamicImage,
+        progress: &dyn Progress,
+        cancel: &CancelToken,
+    ) -> Result<DynamicImage, Cancelled> {
+        let color = img.color();
+        self.try_sort_buffer(into_rgba(img), progress, cancel)
+            .map(|rgba| from_rgba(rgba, color))
+    }
+
+    /// Apply every pass in order to an owned buffer of any
+    /// [`Channel`](trait.Channel.html) type.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err(Cancelled)` if the token was cancelled before the last pass finished.
+    pub fn try_sort_buffer<T: Channel>(
+        &self,
+        buffer: ImageBuffer<Rgba<T>, Vec<T>>,
+        progress: &dyn Progress,
+        cancel: &CancelToken,
+    ) -> Result<ImageBuffer<Rgba<T>, Vec<T>>, Cancelled> {
+        self.passes.iter().try_fold(buffer, |buffer, pass| {
+            pass.try_sort_buffer(buffer, progress, cancel)
+        })
+    }
+
+    /// Apply every pass in order to each frame of an animation, keeping the
+    /// frame delays and loop count.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err(Cancelled)` if the token was cancelled before the last frame finished.
+    pub fn try_sort_animation(
+        &self,
+        animation: Animation,
+        progress: &dyn Progress,
+        cancel: &CancelToken,
+    ) -> Result<Animation, Cancelled> {
+        self.try_sweep_animation(animation, &[], progress, cancel)
+    }
+
+    /// Sort each frame of an animation with every sweep interpolated across
+    /// the frames, from its start value on the first to its end value on the
+    /// last. Sweeps are applied to every pass.
+    ///
+    /// To animate a still image, repeat it with
+    /// [`Animation::from_still`](struct.Animation.html#method.from_still) first.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err(Cancelled)` if the token was cancelled before the last frame finished.
+    #[allow(clippy::cast_precision_loss)]
+    pub fn try_sweep_animation(
+        &self,
+        animation: Animation,
+        sweeps: &[Sweep],
+        progress: &dyn Progress,
+        cancel: &CancelToken,
+    ) -> Result<Animation, Cancelled> {
+        let last = animation.frames.len().saturating_sub(1).max(1) as f32;
+
+        Ok(Animation {
+            frames: animation
+                .frames
+                .into_iter()
+                .enumerate()
+                .map(|(idx, Frame { image, delay })| {
+                    let t = idx as f32 / last;
+                    self.at(sweeps, t)
+                        .try_sort_buffer(image, progress, cancel)
+                        .map(|image| Frame { image, delay })
+                })
+                .collect::<Result<_, _>>()?,
+            repeat: animation.repeat,
+        })
+    }
+
+    /// This pipeline with every sweep set to its value at `t`.
+    fn at(&self, sweeps: &[Sweep], t: f32) -> Self {
+        self.passes
+            .iter()
+            .map(|pass| {
+                let mut pass = pass.clone();
+                for sweep in sweeps {
+                    sweep.apply(&mut pass, t);
+                }
+                pass
+            })
+            .collect()
+    }
+
+    /// Record every pass as it happens, each as `frames` frames of one
+    /// animation. See [`Config::try_sort_progressive`](struct.Config.html#method.try_sort_progressive).
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err(Cancelled)` if the token was cancelled before the last pass finished.
+    pub fn try_sort_progressive(
+        &self,
+        buffer: RgbaImage,
+        frames: usize,
+        delay: u16,
+        progress: &dyn Progress,
+        cancel: &CancelToken,
+    ) -> Result<Animation, Cancelled> {
+        let mut animation = Animation::from_still(buffer, 1, delay);
+
+        for pass in &self.passes {
+            let last = match animation.frames.pop() {
+                Some(frame) => frame.image,
+                None => unreachable!("every recording ends with the sorted image"),
+            };
+            let recording = pass.try_sort_progressive(last, frames, delay, progress, cancel)?;
+            animation.frames.extend(recording.frames);
+        }
+
+        Ok(animation)
+    }
+
+    /// Apply every pass in order to an image deeper than 8 bits, keeping its
+    /// full precision.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err(Cancelled)` if the token was cancelled before the last pass finished.
+    pub fn try_sort_deep(
+        &self,
+        img: DeepImage,
+        progress: &dyn Progress,
+        cancel: &CancelToken,
+    ) -> Result<DeepImage, Cancelled> {
+        Ok(match img {
+            DeepImage::Rgba16(buffer, color) => {
+                DeepImage::Rgba16(self.try_sort_buffer(buffer, progress, cancel)?, color)
+            }
+            DeepImage::RgbaF32(buffer) => {
+                DeepImage::RgbaF32(self.try_sort_buffer(buffer, progress, cancel)?)
+            }
+        })
+    }
+}
+
+impl From<Config> for Pipeline {
+    fn from(pass: Config) -> Self {
+        Self { passes: vec![pass] }
+    }
+}
+
+impl FromIterator<Config> for Pipeline {
+    fn from_iter<I: IntoIterator<Item = Config>>(iter: I) -> Self {
+        Self {
+            passes: iter.into_iter().collect(),
+        }
+    }
+}
+
+impl Extend<Config> for Pipeline {
+    fn extend<I: IntoIterator<Item = Config>>(&mut self, iter: I) {
+        self.passes.extend(iter);
+    }
+}
+
+impl FromStr for Pipeline {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.lines()
+            .enumerate()
+            .map(|(idx, line)| (idx, line.trim()))
+            .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+            .map(|(idx, line)| {
+                line.parse()
+                    .map_err(|e| format!("Invalid pass on line {}: {}", idx + 1, e))
+            })
+            .collect()
+    }
+}
+
+impl fmt::Display for Pipeline {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for pass in &self.passes {
+            writeln!(f, "{}", pass)?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,19 @@
+/// Receiver for progress updates while an image is being sorted.
+///
+/// Each pass reports a single stage: `start` once with the number of paths it
+/// will traverse, `inc` as paths are completed, and `finish` at the end.
+pub trait Progress {
+    /// A stage with `len` steps has started.
+    fn start(&self, label: &str, len: u64);
+    /// `delta` more steps of the current stage are complete.
+    fn inc(&self, delta: u64);
+    /// The current stage is complete.
+    fn finish(&self);
+}
+
+/// Discard all progress updates.
+impl Progress for () {
+    fn start(&self, _: &str, _: u64) {}
+    fn inc(&self, _: u64) {}
+    fn finish(&self) {}
+}
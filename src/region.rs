@@ -0,0 +1,246 @@
+use std::fmt;
+use std::str::FromStr;
+
+use crate::path::unwrap_parens;
+
+/// A distance in pixels or as a percentage of the image size.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Coord {
+    Pixels(f32),
+    Percent(f32),
+}
+
+impl Coord {
+    /// The distance in pixels along a side of length `len`.
+    #[allow(clippy::cast_precision_loss)]
+    fn resolve(self, len: u32) -> f32 {
+        match self {
+            Coord::Pixels(px) => px,
+            Coord::Percent(pct) => pct / 100. * len as f32,
+        }
+    }
+}
+
+impl FromStr for Coord {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let st = s.trim();
+        let err_msg = || format!("Could not parse `{}` as a coordinate", st);
+
+        if let Some(pct) = st.strip_suffix('%') {
+            pct.trim()
+                .parse()
+                .map(Coord::Percent)
+                .map_err(|_| err_msg())
+        } else {
+            st.parse().map(Coord::Pixels).map_err(|_| err_msg())
+        }
+    }
+}
+
+impl fmt::Display for Coord {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Coord::Pixels(px) => write!(f, "{}", px),
+            Coord::Percent(pct) => write!(f, "{}%", pct),
+        }
+    }
+}
+
+/// Part of an image to sort. Pixels outside it are left untouched.
+///
+/// Written as `x,y,w,h` or `rect(x,y,w,h)` for a rectangle, or
+/// `polygon(x1,y1,x2,y2,x3,y3,...)` for a polygon. Each coordinate is in
+/// pixels, or a percentage of the image size if it ends with `%`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Region {
+    Rect {
+        x: Coord,
+        y: Coord,
+        width: Coord,
+        height: Coord,
+    },
+    Polygon(Vec<(Coord, Coord)>),
+}
+
+impl Region {
+    /// Which pixels of an image are inside the region, in row-major order.
+    #[allow(clippy::cast_precision_loss)]
+    pub(crate) fn mask(&self, img_w: u32, img_h: u32) -> Vec<bool> {
+        let resolve = |(x, y): &(Coord, Coord)| (x.resolve(img_w), y.resolve(img_h));
+
+        match self {
+            Region::Rect {
+                x,
+                y,
+                width,
+                height,
+            } => {
+                let (left, top) = resolve(&(*x, *y));
+                let (right, bottom) = (left + width.resolve(img_w), top + height.resolve(img_h));
+
+                (0..img_h)
+                    .flat_map(|py| (0..img_w).map(move |px| (px as f32, py as f32)))
+                    .map(|(px, py)| px >= left && px < right && py >= top && py < bottom)
+                    .collect()
+            }
+            Region::Polygon(points) => {
+                let points = points.iter().map(resolve).collect::<Vec<_>>();
+
+                (0..img_h)
+                    .flat_map(|py| (0..img_w).map(move |px| (px as f32 + 0.5, py as f32 + 0.5)))
+                    .map(|pt| contains(&points, pt))
+                    .collect()
+            }
+        }
+    }
+}
+
+/// Whether a point is inside a polygon, by the even-odd rule.
+fn contains(points: &[(f32, f32)], (x, y): (f32, f32)) -> bool {
+    let edges = points.iter().zip(points.iter().cycle().skip(1));
+
+    edges
+        .filter(|((_, y0), (_, y1))| (*y0 > y) != (*y1 > y))
+        .filter(|((x0, y0), (x1, y1))| x < x0 + (y - y0) / (y1 - y0) * (x1 - x0))
+        .count()
+        % 2
+        == 1
+}
+
+impl FromStr for Region {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let st = s.trim();
+        let err_msg = format!("Could not parse `{}` as a valid region", st);
+
+        let (is_polygon, args) = if let Some(args) = st.strip_prefix("polygon") {
+            (true, unwrap_parens(args).map_err(|()| err_msg.clone())?)
+        } else if let Some(args) = st.strip_prefix("rect") {
+            (false, unwrap_parens(args).map_err(|()| err_msg.clone())?)
+        } else {
+            (false, st)
+        };
+        let args = args
+            .split(',')
+            .map(str::parse)
+            .collect::<Result<Vec<Coord>, _>>()?;
+
+        match args.len() {
+            4 if !is_polygon => Ok(Region::Rect {
+                x: args[0],
+                y: args[1],
+                width: args[2],
+                height: args[3],
+            }),
+            n if is_polygon && n >= 6 && n % 2 == 0 => Ok(Region::Polygon(
+                args.chunks(2).map(|pt| (pt[0], pt[1])).collect(),
+            )),
+            _ => Err(err_msg),
+        }
+    }
+}
+
+impl fmt::Display for Region {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Region::Rect {
+                x,
+                y,
+                width,
+                height,
+            } => write!(f, "rect({},{},{},{})", x, y, width, height),
+            Region::Polygon(points) => {
+                let coords = points
+                    .iter()
+                    .map(|(x, y)| format!("{},{}", x, y))
+                    .collect::<Vec<_>>();
+                write!(f, "polygon({})", coords.join(","))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Draw a mask as rows of `#` for pixels inside and `.` for outside.
+    fn draw(region: &str, width: u32, height: u32) -> Vec<String> {
+        let region = region.parse::<Region>().expect("valid region");
+        region
+            .mask(width, height)
+            .chunks(width as usize)
+            .map(|row| row.iter().map(|&m| if m { '#' } else { '.' }).collect())
+            .collect()
+    }
+
+    #[test]
+    fn parse() {
+        let rect = Region::Rect {
+            x: Coord::Pixels(4.),
+            y: Coord::Pixels(2.),
+            width: Coord::Percent(50.),
+            height: Coord::Percent(12.5),
+        };
+        assert_eq!("4,2,50%,12.5%".parse(), Ok(rect.clone()));
+        assert_eq!(" rect( 4, 2 ,50 %, 12.5%) ".parse(), Ok(rect.clone()));
+        assert_eq!(rect.to_string().parse(), Ok(rect));
+
+        let polygon = Region::Polygon(vec![
+            (Coord::Pixels(0.), Coord::Pixels(0.)),
+            (Coord::Percent(50.), Coord::Pixels(10.)),
+            (Coord::Pixels(20.), Coord::Percent(100.)),
+        ]);
+        assert_eq!("polygon(0,0,50%,10,20,100%)".parse(), Ok(polygon.clone()));
+        assert_eq!(polygon.to_string().parse(), Ok(polygon));
+
+        for bad in &[
+            "1,2,3",
+            "1,2,3,4,5",
+            "rect(1,2,3,x)",
+            "rect(1,2,3,4",
+            "polygon(0,0,1,1)",
+            "polygon(0,0,1,1,2,2,3)",
+            "polygon(1,2,3,4,5,6",
+            "circle(1,2,3,4)",
+        ] {
+            assert!(bad.parse::<Region>().is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn percentages_follow_image_size() {
+        assert_eq!(
+            draw("25%,50%,50%,25%", 8, 4),
+            ["........", "........", "..####..", "........"]
+        );
+        assert_eq!(
+            draw("25%,50%,50%,25%", 4, 8),
+            ["....", "....", "....", "....", ".##.", ".##.", "....", "...."]
+        );
+        assert_eq!(draw("rect(1,0,50%,1)", 4, 2), [".##.", "...."]);
+    }
+
+    #[test]
+    fn polygon_even_odd() {
+        assert_eq!(
+            draw("polygon(0,0,4,0,0,4)", 4, 4),
+            ["###.", "##..", "#...", "...."]
+        );
+
+        // the inner square overlaps the outer one, so by the even-odd rule it
+        // is a hole whichever way it winds
+        let hole = ["######", "######", "##..##", "##..##", "######", "######"];
+        assert_eq!(
+            draw("polygon(0,0,6,0,6,6,0,6,0,0,2,2,4,2,4,4,2,4,2,2)", 6, 6),
+            hole
+        );
+        assert_eq!(
+            draw("polygon(0,0,6,0,6,6,0,6,0,0,2,2,2,4,4,4,4,2,2,2)", 6, 6),
+            hole
+        );
+    }
+}
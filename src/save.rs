@@ -0,0 +1,157 @@
+use std::ffi::OsStr;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use image::{DynamicImage, GenericImageView, ImageFormat, ImageOutputFormat, ImageResult};
+use png::HasParameters;
+use strum_macros::{Display, EnumString};
+
+/// How hard to compress PNG data.
+#[derive(Clone, Copy, Debug, Display, EnumString, Eq, PartialEq)]
+#[strum(serialize_all = "snake_case")]
+pub enum Compression {
+    /// Balanced speed and size.
+    Default,
+    /// Fast, with minimal compression.
+    Fast,
+    /// Smallest output, slowest to write.
+    Best,
+    /// Huffman coding only.
+    Huffman,
+    /// Run-length encoding only.
+    Rle,
+}
+
+/// Filter applied to each row of a PNG before it is compressed.
+#[derive(Clone, Copy, Debug, Display, EnumString, Eq, PartialEq)]
+#[strum(serialize_all = "snake_case")]
+pub enum Filter {
+    /// Store rows as they are.
+    None,
+    /// Difference from the pixel to the left.
+    Sub,
+    /// Difference from the pixel above.
+    Up,
+    /// Difference from the average of the left and upper pixels.
+    Avg,
+    /// Difference from a prediction based on three neighbours.
+    Paeth,
+}
+
+/// Encoder settings for saving an image.
+///
+/// The defaults produce the same files as `DynamicImage::save`. WebP and TIFF
+/// cannot be written, as the `image` crate has no encoders for them.
+#[derive(Clone, Copy, Debug)]
+pub struct SaveOptions {
+    /// Format to write, or `None` to choose by the file extension.
+    pub format: Option<ImageFormat>,
+    /// JPEG quality, from 1 to 100.
+    pub jpeg_quality: u8,
+    /// How hard to compress PNG data.
+    pub png_compression: Compression,
+    /// Filter applied to PNG rows before compression.
+    pub png_filter: Filter,
+}
+
+impl Default for SaveOptions {
+    fn default() -> Self {
+        Self {
+            format: None,
+            jpeg_quality: 75,
+            png_compression: Compression::Fast,
+            png_filter: Filter::Sub,
+        }
+    }
+}
+
+/// Save an image to a file with the given encoder settings.
+///
+/// Without a format in `options`, the format is chosen by the file extension
+/// as `DynamicImage::save` does.
+///
+/// # Errors
+///
+/// Returns an error if the format cannot be determined or written, or if
+/// writing fails.
+pub fn save_with_options<P: AsRef<Path>>(
+    img: &DynamicImage,
+    path: P,
+    options: &SaveOptions,
+) -> ImageResult<()> {
+    let path = path.as_ref();
+    match options.format.or_else(|| tunable_format(path)) {
+        Some(f) => write_with_options(img, &mut BufWriter::new(File::create(path)?), f, options),
+        None => Ok(img.save(path)?),
+    }
+}
+
+/// Encode an image in a format with the given encoder settings. The format
+/// in `options` is ignored.
+///
+/// # Errors
+///
+/// Returns an error if the format cannot be written or writing fails.
+pub fn write_with_options<W: Write>(
+    img: &DynamicImage,
+    w: &mut W,
+    format: ImageFormat,
+    options: &SaveOptions,
+) -> ImageResult<()> {
+    match format {
+        ImageFormat::PNG => write_png(img, w, *options),
+        ImageFormat::JPEG => img.write_to(w, ImageOutputFormat::JPEG(options.jpeg_quality)),
+        f => img.write_to(w, f),
+    }
+}
+
+fn write_png<W: Write>(img: &DynamicImage, w: &mut W, options: SaveOptions) -> ImageResult<()> {
+    // PNG has no BGR channel orders
+    let img = match img {
+        DynamicImage::ImageBgr8(_) => DynamicImage::ImageRgb8(img.to_rgb()),
+        DynamicImage::ImageBgra8(_) => DynamicImage::ImageRgba8(img.to_rgba()),
+        _ => img.clone(),
+    };
+    let (width, height) = img.dimensions();
+    let (color, depth) = img.color().into();
+
+    let mut encoder = png::Encoder::new(w, width, height);
+    encoder
+        .set(color)
+        .set(depth)
+        .set(match options.png_compression {
+            Compression::Default => png::Compression::Default,
+            Compression::Fast => png::Compression::Fast,
+            Compression::Best => png::Compression::Best,
+            Compression::Huffman => png::Compression::Huffman,
+            Compression::Rle => png::Compression::Rle,
+        })
+        .set(match options.png_filter {
+            Filter::None => png::FilterType::NoFilter,
+            Filter::Sub => png::FilterType::Sub,
+            Filter::Up => png::FilterType::Up,
+            Filter::Avg => png::FilterType::Avg,
+            Filter::Paeth => png::FilterType::Paeth,
+        });
+    let mut writer = encoder.write_header().map_err(io::Error::from)?;
+    writer
+        .write_image_data(&img.raw_pixels())
+        .map_err(io::Error::from)?;
+
+    Ok(())
+}
+
+/// The format of a file extension, if it has any encoder settings.
+fn tunable_format(path: &Path) -> Option<ImageFormat> {
+    match path
+        .extension()
+        .and_then(OsStr::to_str)?
+        .to_ascii_lowercase()
+        .as_str()
+    {
+        "jpg" | "jpeg" => Some(ImageFormat::JPEG),
+        "png" => Some(ImageFormat::PNG),
+        _ => None,
+    }
+}
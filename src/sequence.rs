@@ -0,0 +1,110 @@
+use image::{DynamicImage, ImageBuffer, Rgba};
+
+use crate::{from_rgba, into_rgba, CancelToken, Cancelled, Channel, Config, Pipeline, Progress};
+
+/// Sorts the frames of a video one after another, keeping spans stable
+/// between frames.
+///
+/// Sorting each frame on its own makes span boundaries flicker wherever a
+/// pixel's value hovers around a threshold. Here a pixel only enters the
+/// sorted range once it is `hysteresis` inside it, and only leaves once it
+/// is `hysteresis` outside it, so the spans from the previous frame are
+/// reused until the image really changes.
+///
+/// Frames must be sorted in order. A frame with different dimensions starts
+/// afresh.
+pub struct Sequence {
+    pipeline: Pipeline,
+    hysteresis: u8,
+    /// Which pixels were in range for each pass of the last frame.
+    ranges: Vec<Vec<bool>>,
+}
+
+impl Sequence {
+    /// Prepare to sort frames with a pipeline, using a `hysteresis` margin
+    /// on the same scale as the thresholds.
+    #[must_use]
+    pub fn new(pipeline: Pipeline, hysteresis: u8) -> Self {
+        Self {
+            pipeline,
+            hysteresis,
+            ranges: Vec::new(),
+        }
+    }
+
+    /// Sort the next frame. The result has the same colour type as the frame.
+    #[must_use]
+    pub fn sort_frame(&mut self, img: DynamicImage) -> DynamicImage {
+        let color = img.color();
+        match self.try_sort_frame(into_rgba(img), &(), &CancelToken::new()) {
+            Ok(rgba) => from_rgba(rgba, color),
+            Err(Cancelled) => unreachable!("no other handle to the token exists"),
+        }
+    }
+
+    /// Sort the next frame, an owned buffer of any
+    /// [`Channel`](trait.Channel.html) type.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err(Cancelled)` if the token was cancelled before the last
+    /// pass finished. The next frame then starts afresh.
+    pub fn try_sort_frame<T: Channel>(
+        &mut self,
+        mut buffer: ImageBuffer<Rgba<T>, Vec<T>>,
+        progress: &dyn Progress,
+        cancel: &CancelToken,
+    ) -> Result<ImageBuffer<Rgba<T>, Vec<T>>, Cancelled> {
+        let len = (buffer.width() * buffer.height()) as usize;
+        let previous = std::mem::take(&mut self.ranges);
+
+        for (idx, pass) in self.pipeline.passes().iter().enumerate() {
+            let last = previous.get(idx).filter(|r| r.len() == len);
+            let ranges = in_range(pass, &buffer, last, self.hysteresis);
+            let mask_fn = pass.mask_fn();
+            let mask = buffer
+                .pixels()
+                .zip(&ranges)
+                .map(|(p, &r)| r != pass.invert && mask_fn(p))
+                .collect::<Vec<_>>();
+
+            buffer = pass.try_sort_masked(buffer, Some(&mask), progress, cancel)?;
+            self.ranges.push(ranges);
+        }
+
+        Ok(buffer)
+    }
+}
+
+/// Find the pixels in a pass's range, widening it for pixels that were in
+/// range last frame and narrowing it for those that were not.
+fn in_range<T: Channel>(
+    config: &Config,
+    buffer: &ImageBuffer<Rgba<T>, Vec<T>>,
+    last: Option<&Vec<bool>>,
+    hysteresis: u8,
+) -> Vec<bool> {
+    let key_fn = T::key_fn(config.function);
+    let (min, max) = (config.minimum, config.maximum);
+    let (wide_min, wide_max) = (
+        T::from_threshold(min.saturating_sub(hysteresis)),
+        T::from_threshold(max.saturating_add(hysteresis)),
+    );
+    let (narrow_min, narrow_max) = (
+        T::from_threshold(min.saturating_add(hysteresis)),
+        T::from_threshold(max.saturating_sub(hysteresis)),
+    );
+
+    buffer
+        .pixels()
+        .enumerate()
+        .map(|(idx, p)| {
+            let l = key_fn(p);
+            match last.map(|r| r[idx]) {
+                Some(true) => l >= wide_min && l <= wide_max,
+                Some(false) => l >= narrow_min && l <= narrow_max,
+                None => l >= T::from_threshold(min) && l <= T::from_threshold(max),
+            }
+        })
+        .collect()
+}
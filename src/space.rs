@@ -0,0 +1,256 @@
+use std::fmt;
+use std::str::FromStr;
+
+use image::Rgba;
+use strum_macros::{Display, EnumString};
+
+use crate::order::Arrangement;
+use crate::Channel;
+
+/// Colour space that pixels are converted into before a channel is sorted.
+#[derive(Clone, Copy, Debug, Display, EnumString, Eq, PartialEq)]
+#[strum(serialize_all = "snake_case")]
+pub enum ColorSpace {
+    /// Hue, saturation and value.
+    Hsv,
+    /// Hue, saturation and lightness.
+    Hsl,
+    /// Luma and two chroma differences, as used by JPEG.
+    #[strum(serialize = "ycbcr")]
+    YCbCr,
+    /// CIE L*a*b*, with lightness and two opponent colour axes.
+    Lab,
+}
+
+impl ColorSpace {
+    /// Names of the three channels, in order.
+    fn channel_names(self) -> [&'static str; 3] {
+        match self {
+            ColorSpace::Hsv => ["h", "s", "v"],
+            ColorSpace::Hsl => ["h", "s", "l"],
+            ColorSpace::YCbCr => ["y", "cb", "cr"],
+            ColorSpace::Lab => ["l", "a", "b"],
+        }
+    }
+
+    /// Convert RGB values to this space.
+    #[allow(clippy::many_single_char_names)]
+    fn encode(self, rgb: [f32; 3]) -> [f32; 3] {
+        match self {
+            ColorSpace::Hsv => {
+                let (h, max, chroma) = hue_max_chroma(rgb);
+                let s = if max > 0. { chroma / max } else { 0. };
+                [h, s, max]
+            }
+            ColorSpace::Hsl => {
+                let (h, max, chroma) = hue_max_chroma(rgb);
+                let l = max - chroma / 2.;
+                let s = if l > 0. && l < 1. {
+                    chroma / (1. - (2. * l - 1.).abs())
+                } else {
+                    0.
+                };
+                [h, s, l]
+            }
+            ColorSpace::YCbCr => {
+                let [r, g, b] = rgb;
+                let y = 0.299 * r + 0.587 * g + 0.114 * b;
+                [y, 0.5 + (b - y) * 0.564, 0.5 + (r - y) * 0.713]
+            }
+            ColorSpace::Lab => {
+                let [r, g, b] = rgb.map(to_linear);
+                let xyz = [
+                    (0.412_456_4 * r + 0.357_576_1 * g + 0.180_437_5 * b) / WHITE[0],
+                    (0.212_672_9 * r + 0.715_152_2 * g + 0.072_175 * b) / WHITE[1],
+                    (0.019_333_9 * r + 0.119_192 * g + 0.950_304_1 * b) / WHITE[2],
+                ];
+                let [fx, fy, fz] = xyz.map(lab_f);
+                [116. * fy - 16., 500. * (fx - fy), 200. * (fy - fz)]
+            }
+        }
+    }
+
+    /// Convert values in this space back to RGB.
+    #[allow(clippy::many_single_char_names)]
+    fn decode(self, values: [f32; 3]) -> [f32; 3] {
+        match self {
+            ColorSpace::Hsv => {
+                let [h, s, v] = values;
+                from_hue(h, v * s, v - v * s)
+            }
+            ColorSpace::Hsl => {
+                let [h, s, l] = values;
+                let chroma = (1. - (2. * l - 1.).abs()) * s;
+                from_hue(h, chroma, l - chroma / 2.)
+            }
+            ColorSpace::YCbCr => {
+                let [y, cb, cr] = values;
+                [
+                    y + 1.403 * (cr - 0.5),
+                    y - 0.344 * (cb - 0.5) - 0.714 * (cr - 0.5),
+                    y + 1.773 * (cb - 0.5),
+                ]
+            }
+            ColorSpace::Lab => {
+                let [l, a, b] = values;
+                let fy = (l + 16.) / 116.;
+                let [x, y, z] = [fy + a / 500., fy, fy - b / 200.].map(lab_f_inv);
+                let (x, y, z) = (x * WHITE[0], y * WHITE[1], z * WHITE[2]);
+                [
+                    3.240_454_2 * x - 1.537_138_5 * y - 0.498_531_4 * z,
+                    -0.969_266 * x + 1.876_010_8 * y + 0.041_556 * z,
+                    0.055_643_4 * x - 0.204_025_9 * y + 1.057_225_2 * z,
+                ]
+                .map(from_linear)
+            }
+        }
+    }
+}
+
+/// D65 reference white in XYZ.
+const WHITE: [f32; 3] = [0.950_47, 1.0, 1.088_83];
+
+/// Hue as a fraction of a turn, with the largest channel value and chroma.
+fn hue_max_chroma([r, g, b]: [f32; 3]) -> (f32, f32, f32) {
+    let max = r.max(g).max(b);
+    let chroma = max - r.min(g).min(b);
+
+    let h = if chroma <= 0. {
+        0.
+    } else if (max - r).abs() < f32::EPSILON {
+        ((g - b) / chroma).rem_euclid(6.)
+    } else if (max - g).abs() < f32::EPSILON {
+        (b - r) / chroma + 2.
+    } else {
+        (r - g) / chroma + 4.
+    };
+
+    (h / 6., max, chroma)
+}
+
+/// RGB from a hue, chroma and the value added to every channel.
+#[allow(clippy::many_single_char_names)]
+fn from_hue(h: f32, chroma: f32, base: f32) -> [f32; 3] {
+    let h = h.rem_euclid(1.) * 6.;
+    let x = chroma * (1. - (h % 2. - 1.).abs());
+
+    let [r, g, b] = match h {
+        h if h < 1. => [chroma, x, 0.],
+        h if h < 2. => [x, chroma, 0.],
+        h if h < 3. => [0., chroma, x],
+        h if h < 4. => [0., x, chroma],
+        h if h < 5. => [x, 0., chroma],
+        _ => [chroma, 0., x],
+    };
+
+    [r + base, g + base, b + base]
+}
+
+fn to_linear(c: f32) -> f32 {
+    if c <= 0.040_45 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn from_linear(c: f32) -> f32 {
+    if c <= 0.003_130_8 {
+        c * 12.92
+    } else {
+        1.055 * c.powf(1. / 2.4) - 0.055
+    }
+}
+
+fn lab_f(t: f32) -> f32 {
+    if t > 216. / 24389. {
+        t.cbrt()
+    } else {
+        t * 24389. / 27. / 116. + 16. / 116.
+    }
+}
+
+fn lab_f_inv(t: f32) -> f32 {
+    if t.powi(3) > 216. / 24389. {
+        t.powi(3)
+    } else {
+        (t * 116. - 16.) * 27. / 24389.
+    }
+}
+
+/// One channel of a colour space, written as `space.channel`, e.g. `hsv.h`
+/// for hue or `lab.l` for lightness.
+///
+/// When sorting by a space channel, each span is converted into the space,
+/// only the chosen channel is sorted, and the other two stay in place before
+/// the span is converted back.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct SpaceChannel {
+    pub space: ColorSpace,
+    /// Index of the channel, from 0 to 2.
+    pub channel: usize,
+}
+
+impl SpaceChannel {
+    /// Arrange the chosen channel of a span by its own value.
+    pub(crate) fn arrange<T: Channel>(self, pixels: &mut [Rgba<T>], arrangement: &mut Arrangement) {
+        let mut values = pixels
+            .iter()
+            .map(|p| {
+                let rgb = [p.data[0], p.data[1], p.data[2]].map(Channel::to_f32);
+                self.space.encode(rgb)
+            })
+            .collect::<Vec<_>>();
+
+        let mut sorted = values.iter().map(|v| v[self.channel]).collect::<Vec<_>>();
+        arrangement.arrange(&mut sorted, f32::compare);
+
+        for ((p, v), s) in pixels.iter_mut().zip(&mut values).zip(sorted) {
+            v[self.channel] = s;
+            let rgb = self.space.decode(*v);
+            for (c, value) in p.data.iter_mut().zip(&rgb) {
+                *c = T::from_f32(*value);
+            }
+        }
+    }
+}
+
+impl FromStr for SpaceChannel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let st = s.trim().to_ascii_lowercase();
+        let err_msg = || {
+            format!(
+                "Could not parse `{}` as a colour space channel, e.g. `hsv.h`",
+                s.trim()
+            )
+        };
+
+        let mut parts = st.splitn(2, '.');
+        let space = parts
+            .next()
+            .and_then(|sp| sp.parse::<ColorSpace>().ok())
+            .ok_or_else(err_msg)?;
+        let name = parts.next().ok_or_else(err_msg)?;
+        let channel = space
+            .channel_names()
+            .iter()
+            .position(|&n| n == name)
+            .or_else(|| name.parse().ok().filter(|&idx: &usize| idx < 3))
+            .ok_or_else(err_msg)?;
+
+        Ok(Self { space, channel })
+    }
+}
+
+impl fmt::Display for SpaceChannel {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}.{}",
+            self.space,
+            self.space.channel_names()[self.channel]
+        )
+    }
+}
@@ -0,0 +1,38 @@
+use image::Rgba;
+use strum_macros::{Display, EnumString};
+
+use crate::order::Arrangement;
+use crate::Channel;
+
+/// Whether pixels are moved whole or their channels are sorted separately.
+#[derive(Clone, Copy, Debug, Display, EnumString, Eq, PartialEq)]
+#[strum(serialize_all = "snake_case")]
+pub enum Split {
+    /// Move whole pixels, ordered by the sort heuristic.
+    None,
+    /// Sort the red, green and blue values of each span independently,
+    /// leaving alpha in place.
+    Rgb,
+    /// Sort all four channels of each span independently.
+    Rgba,
+}
+
+impl Split {
+    /// Arrange each channel of a span by its own value.
+    pub(crate) fn arrange<T: Channel>(self, pixels: &mut [Rgba<T>], arrangement: &mut Arrangement) {
+        let channels = match self {
+            Split::None => 0,
+            Split::Rgb => 3,
+            Split::Rgba => 4,
+        };
+
+        for c in 0..channels {
+            let mut values = pixels.iter().map(|p| p.data[c]).collect::<Vec<_>>();
+            arrangement.arrange(&mut values, T::compare);
+
+            for (p, v) in pixels.iter_mut().zip(values) {
+                p.data[c] = v;
+            }
+        }
+    }
+}
@@ -0,0 +1,186 @@
+use std::fmt;
+use std::str::FromStr;
+
+use crate::{Config, Shape};
+
+/// A sorting parameter that can change over the frames of an animation.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Param {
+    /// Lower end of the sorted range.
+    Minimum,
+    /// Upper end of the sorted range.
+    Maximum,
+    /// Direction of the path, in degrees.
+    Angle,
+    /// Amplitude of a sine path.
+    Amplitude,
+    /// Wavelength of a sine path.
+    Lambda,
+    /// Phase offset of a sine path.
+    Offset,
+    /// Eccentricity of an ellipse path.
+    Eccentricity,
+    /// Horizontal position of an ellipse's center.
+    CenterX,
+    /// Vertical position of an ellipse's center.
+    CenterY,
+}
+
+impl FromStr for Param {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "min" | "minimum" => Ok(Param::Minimum),
+            "max" | "maximum" => Ok(Param::Maximum),
+            "angle" => Ok(Param::Angle),
+            "amplitude" => Ok(Param::Amplitude),
+            "lambda" | "wavelength" => Ok(Param::Lambda),
+            "offset" => Ok(Param::Offset),
+            "eccentricity" => Ok(Param::Eccentricity),
+            "center-x" | "cx" => Ok(Param::CenterX),
+            "center-y" | "cy" => Ok(Param::CenterY),
+            st => Err(format!("Unknown parameter `{}`", st)),
+        }
+    }
+}
+
+impl fmt::Display for Param {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Param::Minimum => "min",
+            Param::Maximum => "max",
+            Param::Angle => "angle",
+            Param::Amplitude => "amplitude",
+            Param::Lambda => "lambda",
+            Param::Offset => "offset",
+            Param::Eccentricity => "eccentricity",
+            Param::CenterX => "center-x",
+            Param::CenterY => "center-y",
+        })
+    }
+}
+
+/// A parameter changing linearly from `start` to `end` over an animation,
+/// written as `param=start..end`, e.g. `min=0..255` or `angle=0..180`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Sweep {
+    /// The parameter to change.
+    pub param: Param,
+    /// Value in the first frame.
+    pub start: f32,
+    /// Value in the last frame.
+    pub end: f32,
+}
+
+impl Sweep {
+    /// Whether this sweep has any effect on `config`'s path shape.
+    #[must_use]
+    pub fn applies_to(&self, config: &Config) -> bool {
+        match self.param {
+            Param::Minimum | Param::Maximum | Param::Angle => true,
+            Param::Amplitude | Param::Lambda | Param::Offset => {
+                matches!(config.path, Shape::Sine { .. })
+            }
+            Param::Eccentricity | Param::CenterX | Param::CenterY => {
+                matches!(config.path, Shape::Ellipse { .. })
+            }
+        }
+    }
+
+    /// Set the parameter to its value at `t`, from `0.0` at the start of the
+    /// sweep to `1.0` at the end.
+    ///
+    /// Linear paths can only be tilted by up to 90 degrees either way, so an
+    /// angle outside `-45..45` is reached by switching between horizontal and
+    /// vertical sorting. Any direction can be swept through this way. Angles
+    /// are relative to the pass's own orientation, so a vertical pass swept
+    /// from 0 starts out vertical.
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    pub fn apply(&self, config: &mut Config, t: f32) {
+        let value = self.start + (self.end - self.start) * t;
+
+        match (self.param, &mut config.path) {
+            (Param::Minimum, _) => config.minimum = value.round().clamp(0., 255.) as u8,
+            (Param::Maximum, _) => config.maximum = value.round().clamp(0., 255.) as u8,
+            (Param::Angle, Shape::Linear) => {
+                let turn = if config.vertical { 90. } else { 0. };
+                let direction = (value + turn + 45.).rem_euclid(180.) - 45.;
+                config.vertical = direction >= 45.;
+                config.angle = if config.vertical {
+                    direction - 90.
+                } else {
+                    direction
+                };
+            }
+            (Param::Angle, _) => config.angle = value,
+            (Param::Amplitude, Shape::Sine { amplitude, .. }) => *amplitude = value,
+            (Param::Lambda, Shape::Sine { lambda, .. }) => *lambda = value,
+            (Param::Offset, Shape::Sine { offset, .. }) => *offset = value,
+            (Param::Eccentricity, Shape::Ellipse { eccentricity, .. }) => *eccentricity = value,
+            (Param::CenterX, Shape::Ellipse { center, .. }) => center.0 = value,
+            (Param::CenterY, Shape::Ellipse { center, .. }) => center.1 = value,
+            _ => (),
+        }
+    }
+}
+
+impl FromStr for Sweep {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err_msg = || format!("Could not parse `{}` as `param=start..end`", s);
+
+        let mut parts = s.splitn(2, '=');
+        let param = parts.next().ok_or_else(err_msg)?.parse()?;
+        let mut range = parts.next().ok_or_else(err_msg)?.splitn(2, "..");
+        let start = range
+            .next()
+            .and_then(|v| v.trim().parse().ok())
+            .ok_or_else(err_msg)?;
+        let end = range
+            .next()
+            .and_then(|v| v.trim().parse().ok())
+            .ok_or_else(err_msg)?;
+
+        Ok(Self { param, start, end })
+    }
+}
+
+impl fmt::Display for Sweep {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}={}..{}", self.param, self.start, self.end)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn swept(vertical: bool, start: f32, end: f32, t: f32) -> (bool, f32) {
+        let mut config = Config {
+            vertical,
+            ..Config::default()
+        };
+        let sweep = format!("angle={}..{}", start, end)
+            .parse::<Sweep>()
+            .expect("valid sweep");
+        sweep.apply(&mut config, t);
+        (config.vertical, config.angle)
+    }
+
+    #[test]
+    fn angle_keeps_orientation() {
+        assert_eq!(swept(false, 0., 30., 1.), (false, 30.));
+        assert_eq!(swept(true, 0., 30., 0.), (true, 0.));
+        assert_eq!(swept(true, 0., 30., 1.), (true, 30.));
+    }
+
+    #[test]
+    fn angle_turns_past_45() {
+        assert_eq!(swept(false, 0., 60., 1.), (true, -30.));
+        assert_eq!(swept(false, 0., 180., 1.), (false, 0.));
+        assert_eq!(swept(true, 0., 60., 1.), (false, -30.));
+        assert_eq!(swept(true, 0., -60., 1.), (false, 30.));
+    }
+}
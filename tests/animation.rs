@@ -0,0 +1,40 @@
+//! Frames, delays and the loop count must survive a GIF round trip, or
+//! sorting an animation would change its timing.
+
+use image::{Rgba, RgbaImage};
+use pxsort::{Animation, Frame, Repeat};
+
+/// A frame split into two flat colours at column `split`, so that the
+/// frames differ but each fits a GIF palette exactly.
+fn frame(split: u32, delay: u16) -> Frame {
+    Frame {
+        image: RgbaImage::from_fn(8, 4, |x, _| {
+            if x < split {
+                Rgba([200, 30, 60, 255])
+            } else {
+                Rgba([10, 120, 250, 255])
+            }
+        }),
+        delay,
+    }
+}
+
+#[test]
+fn gif_round_trip() {
+    for &repeat in &[Repeat::Infinite, Repeat::Finite(0), Repeat::Finite(3)] {
+        let animation = Animation {
+            frames: vec![frame(2, 5), frame(4, 10), frame(6, 20)],
+            repeat,
+        };
+        let mut gif = Vec::new();
+        animation.write_to(&mut gif).expect("encode GIF");
+
+        let loaded = Animation::load(&gif[..]).expect("decode GIF");
+        assert_eq!(loaded.repeat, repeat);
+        assert_eq!(loaded.frames.len(), 3);
+        for (before, after) in animation.frames.iter().zip(&loaded.frames) {
+            assert_eq!(after.delay, before.delay);
+            assert!(*after.image == *before.image, "{:?}", repeat);
+        }
+    }
+}
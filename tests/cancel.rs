@@ -0,0 +1,67 @@
+//! A cancelled token must stop a sort with `Err(Cancelled)` rather than
+//! return a partly sorted image.
+
+use image::DynamicImage;
+use pxsort::{CancelToken, Cancelled, Config, Pipeline, Progress};
+
+mod common;
+
+use common::test_card;
+
+fn cancelled() -> CancelToken {
+    let cancel = CancelToken::new();
+    cancel.cancel();
+    cancel
+}
+
+/// Cancels its token once the first stage is complete.
+struct CancelAfterFirst(CancelToken);
+
+impl Progress for CancelAfterFirst {
+    fn start(&self, _: &str, _: u64) {}
+    fn inc(&self, _: u64) {}
+    fn finish(&self) {
+        self.0.cancel();
+    }
+}
+
+#[test]
+fn config_cancelled_before_sort() {
+    for args in &["--function=luma", "--path=ellipse(0.5,0.5,0.5)"] {
+        let config = args.parse::<Config>().expect("valid pass arguments");
+        let img = DynamicImage::ImageRgba8(test_card(24, 16));
+        assert_eq!(
+            config.try_sort(img, &(), &cancelled()).err(),
+            Some(Cancelled),
+            "{}",
+            args
+        );
+    }
+}
+
+#[test]
+fn pipeline_cancelled_before_sort() {
+    let pipeline = "--function=luma\n--function=hue --vertical"
+        .parse::<Pipeline>()
+        .expect("valid pipeline");
+    let img = DynamicImage::ImageRgba8(test_card(24, 16));
+    assert_eq!(
+        pipeline.try_sort(img, &(), &cancelled()).err(),
+        Some(Cancelled)
+    );
+}
+
+#[test]
+fn pipeline_cancelled_between_passes() {
+    let pipeline = "--function=luma\n--function=hue --vertical"
+        .parse::<Pipeline>()
+        .expect("valid pipeline");
+    let cancel = CancelToken::new();
+    let img = DynamicImage::ImageRgba8(test_card(24, 16));
+    assert_eq!(
+        pipeline
+            .try_sort(img, &CancelAfterFirst(cancel.clone()), &cancel)
+            .err(),
+        Some(Cancelled)
+    );
+}
@@ -0,0 +1,145 @@
+//! Run the command line tool on real files in a scratch directory.
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use image::png::PNGEncoder;
+use image::{ColorType, ImageFormat};
+
+mod common;
+
+/// An empty directory for one test, removed again when dropped.
+struct Scratch(PathBuf);
+
+impl Scratch {
+    fn new(name: &str) -> Self {
+        let dir = env::temp_dir().join(format!("pxsort-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).expect("create scratch directory");
+        Scratch(dir)
+    }
+
+    fn image(&self, name: &str) {
+        common::test_card(16, 8)
+            .save(self.0.join(name))
+            .expect("save test image");
+    }
+
+    fn files(&self) -> Vec<String> {
+        let mut names = fs::read_dir(&self.0)
+            .expect("list scratch directory")
+            .map(|e| {
+                e.expect("directory entry")
+                    .file_name()
+                    .to_string_lossy()
+                    .into_owned()
+            })
+            .collect::<Vec<_>>();
+        names.sort();
+        names
+    }
+}
+
+impl Drop for Scratch {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+/// Run the tool inside `dir`, so paths in `args` are relative to it.
+fn pxsort(dir: &Path, args: &[&str]) {
+    let status = Command::new(env!("CARGO_BIN_EXE_pxsort"))
+        .current_dir(dir)
+        .args(args)
+        .arg("--quiet")
+        .status()
+        .expect("run pxsort");
+    assert!(status.success(), "pxsort {:?} failed", args);
+}
+
+#[test]
+fn rerun_skips_earlier_outputs() {
+    let scratch = Scratch::new("rerun");
+    scratch.image("a.png");
+    scratch.image("b.png");
+
+    pxsort(&scratch.0, &["."]);
+    pxsort(&scratch.0, &["."]);
+    assert_eq!(
+        scratch.files(),
+        ["a.png", "a_1.png", "a_2.png", "b.png", "b_1.png", "b_2.png"]
+    );
+}
+
+#[test]
+fn rerun_with_skip_existing_does_nothing() {
+    let scratch = Scratch::new("skip-existing");
+    scratch.image("a.png");
+    scratch.image("b.png");
+
+    pxsort(&scratch.0, &["--skip-existing", "."]);
+    pxsort(&scratch.0, &["--skip-existing", "."]);
+    assert_eq!(scratch.files(), ["a.png", "a_1.png", "b.png", "b_1.png"]);
+}
+
+#[test]
+fn deep_output() {
+    let scratch = Scratch::new("deep");
+    let samples = (0..16 * 8_u16)
+        .flat_map(|i| vec![i * 500, i * 300, 65535 - i * 400])
+        .flat_map(|s| s.to_be_bytes().to_vec())
+        .collect::<Vec<_>>();
+    let file = fs::File::create(scratch.0.join("deep.png")).expect("create test image");
+    PNGEncoder::new(file)
+        .encode(&samples, 16, 8, ColorType::RGB(16))
+        .expect("save test image");
+
+    // PNG keeps all 16 bits, stored in the header's bit depth byte
+    pxsort(&scratch.0, &["deep.png", "-o", "out.png"]);
+    let png = fs::read(scratch.0.join("out.png")).expect("read PNG output");
+    assert_eq!(png[24], 16);
+
+    // other formats get the image at 8 bits
+    pxsort(&scratch.0, &["deep.png", "-o", "out.jpg"]);
+    let jpeg = fs::read(scratch.0.join("out.jpg")).expect("read JPEG output");
+    assert_eq!(image::guess_format(&jpeg).ok(), Some(ImageFormat::JPEG));
+    image::load_from_memory(&jpeg).expect("decode JPEG output");
+}
+
+#[test]
+fn options_outside_passes_are_rejected() {
+    let status = Command::new(env!("CARGO_BIN_EXE_pxsort"))
+        .args(["--dump-config", "--function", "hue", "--pass", "--vertical"])
+        .stderr(Stdio::null())
+        .status()
+        .expect("run pxsort");
+    assert!(!status.success());
+
+    // the seed is the one option shared by every pass
+    let output = Command::new(env!("CARGO_BIN_EXE_pxsort"))
+        .args(["--dump-config", "--seed", "7", "--pass", "--vertical"])
+        .output()
+        .expect("run pxsort");
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stdout).contains("--seed=7"));
+}
+
+#[test]
+fn sequence_skips_other_files() {
+    let scratch = Scratch::new("sequence");
+    fs::create_dir(scratch.0.join("in")).expect("create frame directory");
+    for frame in &["f1.png", "f2.png", "f10.png"] {
+        scratch.image(&format!("in/{}", frame));
+    }
+    fs::write(scratch.0.join("in/.DS_Store"), b"not an image").expect("write stray file");
+
+    pxsort(&scratch.0, &["--sequence", "in", "-o", "out"]);
+    let mut frames = fs::read_dir(scratch.0.join("out"))
+        .expect("list output directory")
+        .map(|e| e.expect("directory entry").file_name())
+        .collect::<Vec<_>>();
+    frames.sort();
+    assert_eq!(frames, ["f1.png", "f10.png", "f2.png"]);
+}
@@ -0,0 +1,16 @@
+//! Fixtures shared by the integration tests.
+
+use image::{Rgba, RgbaImage};
+
+/// A test card with gradients, repeated values and transparent pixels, so
+/// that ties and masking are exercised.
+pub fn test_card(width: u32, height: u32) -> RgbaImage {
+    RgbaImage::from_fn(width, height, |x, y| {
+        Rgba([
+            (x * 37 + y * 11) as u8,
+            (x * y % 7 * 36) as u8,
+            ((x ^ y) * 9) as u8,
+            if (x + y) % 11 == 0 { 0 } else { 255 },
+        ])
+    })
+}
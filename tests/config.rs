@@ -0,0 +1,67 @@
+//! Dumped configurations must parse back to the same passes, or a saved
+//! `--dump-config` file would not reproduce the image it came from.
+
+use pxsort::{BlendMode, ColorKey, Config, Fill, Heuristic, Order, Pipeline, Region, Shape};
+
+/// A pass with every option moved away from its default.
+fn every_option() -> Config {
+    let mut config = Config::default();
+    config.minimum = 12;
+    config.maximum = 200;
+    config.function = Heuristic::Hue;
+    config.reverse = true;
+    config.order = Order::Rotate(-3);
+    config.strength = 0.25;
+    config.unstable = true;
+    config.seed = 42;
+    config.fill = Fill::Gradient;
+    config.split = "rgba".parse().expect("valid split");
+    config.channel = Some("hsv.s".parse().expect("valid channel"));
+    config.invert = true;
+    config.vertical = true;
+    config.mask_alpha = true;
+    config.alpha_min = 9;
+    config.key = Some(ColorKey([0x12, 0xab, 0xff]));
+    config.key_tolerance = 5;
+    config.angle = -22.5;
+    config.path = Shape::Ellipse {
+        eccentricity: 0.5,
+        center: (0.25, 0.75),
+    };
+    config.region = Some(
+        "polygon(0,0,50%,10,20,100%)"
+            .parse::<Region>()
+            .expect("valid region"),
+    );
+    config.opacity = 0.5;
+    config.blend = BlendMode::Difference;
+    config.feather = 3;
+    config
+}
+
+#[test]
+fn config_round_trip() {
+    let dumped = every_option().to_string();
+    let parsed = dumped.parse::<Config>().expect("dumped pass parses");
+    assert_eq!(parsed.to_string(), dumped);
+
+    let dumped = Config::default().to_string();
+    let parsed = dumped.parse::<Config>().expect("dumped pass parses");
+    assert_eq!(parsed.to_string(), dumped);
+}
+
+#[test]
+fn pipeline_round_trip() {
+    let mut vertical = Config::default();
+    vertical.vertical = true;
+    vertical.function = Heuristic::Luma;
+    vertical.path = "sine(10,20,5)".parse().expect("valid path");
+    let pipeline = vec![every_option(), vertical, Config::default()]
+        .into_iter()
+        .collect::<Pipeline>();
+
+    let dumped = pipeline.to_string();
+    let parsed = dumped.parse::<Pipeline>().expect("dumped pipeline parses");
+    assert_eq!(parsed.passes().len(), 3);
+    assert_eq!(parsed.to_string(), dumped);
+}
@@ -0,0 +1,121 @@
+//! Pin the output of every heuristic and path shape, so that any change to
+//! sorting results is caught. Archival users rely on the same input and
+//! configuration always giving the same image. See `Config` for which
+//! results are the same on every platform.
+
+use image::DynamicImage;
+use pxsort::{Config, Heuristic};
+
+mod common;
+
+/// The card every hash below was taken from.
+fn test_card() -> DynamicImage {
+    DynamicImage::ImageRgba8(common::test_card(48, 32))
+}
+
+/// 64-bit FNV-1a hash of the raw pixel data.
+fn fnv(img: &DynamicImage) -> u64 {
+    img.raw_pixels()
+        .iter()
+        .fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+            (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+        })
+}
+
+fn check(cases: Vec<(String, Config, u64)>) {
+    let failures = cases
+        .into_iter()
+        .map(|(name, config, expected)| (name, fnv(&config.sort(test_card())), expected))
+        .filter(|(_, actual, expected)| actual != expected)
+        .map(|(name, actual, expected)| {
+            format!(
+                "{}: expected {:#018x}, got {:#018x}",
+                name, expected, actual
+            )
+        })
+        .collect::<Vec<_>>();
+
+    assert!(failures.is_empty(), "\n{}", failures.join("\n"));
+}
+
+/// Check cases written as pass arguments.
+fn check_args(cases: &[(&str, u64)]) {
+    check(
+        cases
+            .iter()
+            .map(|&(args, hash)| {
+                let config = args.parse::<Config>().expect("valid pass arguments");
+                (args.to_string(), config, hash)
+            })
+            .collect(),
+    );
+}
+
+#[test]
+fn heuristics() {
+    let expected = [
+        (Heuristic::Luma, 0x6382_df7e_b048_6eb0),
+        (Heuristic::Brightness, 0x5f60_047c_f121_c380),
+        (Heuristic::Max, 0xd7e5_8b66_81b3_5d14),
+        (Heuristic::Min, 0xc73f_beb3_88b4_2810),
+        (Heuristic::Chroma, 0xb143_ed8e_1b49_27c4),
+        (Heuristic::Hue, 0x5ee6_e6f7_b223_5ae8),
+        (Heuristic::Saturation, 0x3fc1_5ad6_3214_6ea8),
+        (Heuristic::Value, 0xd7e5_8b66_81b3_5d14),
+        (Heuristic::Red, 0xc81b_34fa_302d_121c),
+        (Heuristic::Blue, 0x1ad3_22b4_c42c_f970),
+        (Heuristic::Green, 0xf9ce_e665_3453_094c),
+    ];
+    assert_eq!(expected.len(), Heuristic::concrete_variants().count());
+
+    check(
+        expected
+            .iter()
+            .map(|&(function, hash)| {
+                let mut config = Config::default();
+                config.function = function;
+                config.mask_alpha = true;
+                (function.to_string(), config, hash)
+            })
+            .collect(),
+    );
+}
+
+#[test]
+fn lines() {
+    let expected = [
+        ("--path=line", 0x3166_e4b0_f8bc_e1f8),
+        ("--path=line --vertical", 0x0bd3_f014_9708_8c58),
+    ];
+
+    check_args(&expected);
+}
+
+/// These paths go through the platform's trigonometric functions, so their
+/// hashes are only pinned for the target CI runs on.
+#[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+#[test]
+fn curves() {
+    let expected = [
+        ("--path=line --angle=30", 0x389e_b90d_e3dc_f77c),
+        ("--path=sine", 0x4e8c_7c62_ca22_6275),
+        ("--path=sine(10,20,5) --angle=-15", 0x2d38_0b01_0c35_64ac),
+        ("--path=ellipse", 0xa74e_32c9_35f8_16a4),
+        (
+            "--path=ellipse(0.5,0.3,0.7) --angle=45",
+            0xae09_de50_e2d3_737c,
+        ),
+    ];
+
+    check_args(&expected);
+}
+
+#[test]
+fn seeded() {
+    let expected = [
+        ("--order=shuffle --seed=0", 0x444c_4ae9_8e8b_bff0),
+        ("--order=shuffle --seed=42", 0x1cc5_64c1_d409_4140),
+    ];
+
+    check_args(&expected);
+}
@@ -0,0 +1,39 @@
+//! Sorting a buffer in place must give the same image as sorting a copy.
+
+use image::{DynamicImage, ImageBuffer, Rgba};
+use pxsort::Config;
+
+mod common;
+
+use common::test_card;
+
+#[test]
+fn in_place_matches_sort() {
+    let cases = [
+        "--function=luma",
+        "--function=hue --angle=30",
+        "--function=red --vertical",
+        "--function=saturation --vertical --angle=-45 --reverse",
+        "--path=sine(4,8,0) --angle=30",
+        "--path=ellipse(0.5,0.5,0.5) --vertical",
+        "--region=4,2,50%,50% --opacity=0.5 --blend=screen --feather=2",
+    ];
+
+    for args in &cases {
+        let config = args.parse::<Config>().expect("valid pass arguments");
+        let sorted = config
+            .sort(DynamicImage::ImageRgba8(test_card(24, 16)))
+            .to_rgba();
+
+        let mut owned = test_card(24, 16);
+        config.sort_in_place(&mut owned);
+        assert!(*owned == *sorted, "{}", args);
+
+        // a borrowed slice of raw RGBA data sorts the same way
+        let mut raw = test_card(24, 16).into_raw();
+        let mut borrowed =
+            ImageBuffer::<Rgba<u8>, _>::from_raw(24, 16, &mut raw[..]).expect("buffer size");
+        config.sort_in_place(&mut borrowed);
+        assert!(raw == *sorted, "{} on a borrowed slice", args);
+    }
+}
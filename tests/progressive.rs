@@ -0,0 +1,55 @@
+//! A recording must end on exactly the image a plain sort gives, whatever
+//! the number of frames.
+
+use image::DynamicImage;
+use pxsort::{CancelToken, Config, Pipeline};
+
+mod common;
+
+use common::test_card;
+
+const PASSES: &[&str] = &[
+    "--function=luma",
+    "--function=hue --vertical",
+    "--path=sine(4,8,0) --angle=30",
+    "--path=ellipse(0.5,0.5,0.5)",
+    "--opacity=0.5 --blend=screen --feather=2",
+];
+
+#[test]
+fn last_frame_is_sorted() {
+    for args in PASSES {
+        let config = args.parse::<Config>().expect("valid pass arguments");
+        let sorted = config
+            .sort(DynamicImage::ImageRgba8(test_card(24, 16)))
+            .to_rgba();
+
+        for frames in 0..5 {
+            let animation = config
+                .try_sort_progressive(test_card(24, 16), frames, 10, &(), &CancelToken::new())
+                .expect("not cancelled");
+            assert_eq!(animation.frames.len(), frames.max(1), "{} {}", args, frames);
+            let last = &animation.frames[animation.frames.len() - 1].image;
+            assert!(**last == *sorted, "{} with {} frames", args, frames);
+        }
+    }
+}
+
+#[test]
+fn pipeline_last_frame_is_sorted() {
+    let pipeline = PASSES
+        .join("\n")
+        .parse::<Pipeline>()
+        .expect("valid pipeline");
+    let sorted = pipeline
+        .sort(DynamicImage::ImageRgba8(test_card(24, 16)))
+        .to_rgba();
+
+    for frames in 0..5 {
+        let animation = pipeline
+            .try_sort_progressive(test_card(24, 16), frames, 10, &(), &CancelToken::new())
+            .expect("not cancelled");
+        let last = &animation.frames[animation.frames.len() - 1].image;
+        assert!(**last == *sorted, "{} frames", frames);
+    }
+}
@@ -0,0 +1,42 @@
+//! Sorting inside a region must leave every pixel outside it untouched.
+
+use image::DynamicImage;
+use pxsort::Config;
+
+mod common;
+
+use common::test_card;
+
+#[test]
+fn outside_rect_is_unchanged() {
+    // pass arguments, and the pixel bounds of their region on a 24×16 card
+    let cases = [
+        ("--region=4,2,50%,50%", (4, 2, 16, 10)),
+        ("--region=4,2,50%,50% --vertical --reverse", (4, 2, 16, 10)),
+        ("--region=rect(25%,0,8,100%) --angle=30", (6, 0, 14, 16)),
+        (
+            "--region=4,2,50%,50% --path=ellipse(0.5,0.5,0.5)",
+            (4, 2, 16, 10),
+        ),
+        (
+            "--region=4,2,50%,50% --path=sine(4,8,0) --opacity=0.5 --blend=screen",
+            (4, 2, 16, 10),
+        ),
+    ];
+
+    let original = test_card(24, 16);
+    for &(args, (left, top, right, bottom)) in &cases {
+        let config = args.parse::<Config>().expect("valid pass arguments");
+        let sorted = config
+            .sort(DynamicImage::ImageRgba8(original.clone()))
+            .to_rgba();
+
+        let inside = |x, y| x >= left && x < right && y >= top && y < bottom;
+        for (x, y, pixel) in original.enumerate_pixels() {
+            if !inside(x, y) {
+                assert_eq!(sorted.get_pixel(x, y), pixel, "{} at ({}, {})", args, x, y);
+            }
+        }
+        assert!(*sorted != *original, "{} sorted nothing", args);
+    }
+}